@@ -0,0 +1,135 @@
+use std::time::{Duration, Instant};
+
+use opencv::core::{min_max_loc, Mat, MatTraitConst, Rect};
+use opencv::imgproc;
+use uma_details_utility::image::detail::{HorseGirlFullDetailImage, ImageConfig};
+use uma_details_utility::image::{Error, ImageMatrix, SimpleImage};
+
+// Checks that the overlap matcher used by the merge finds the same offsets as the search it
+// replaced, on the same list areas the merge uses, and compares how long both take.
+fn main() -> anyhow::Result<()> {
+    const DIR_PATH: &str = "examples/test_images";
+    const ROUNDS: u32 = 5;
+
+    let config = ImageConfig::default();
+    let matcher = config.overlap_matcher.clone();
+
+    let detail = HorseGirlFullDetailImage::from_path(DIR_PATH, 10, config)?;
+    let list_images: Vec<SimpleImage> = detail
+        .get_factor_list_image()?
+        .get_images()
+        .iter()
+        .map(|image| image.get_list_area_roi())
+        .collect::<Result<_, _>>()?;
+    let list_mats: Vec<Mat> = list_images
+        .iter()
+        .map(|image| image.convert_to_mat())
+        .collect::<Result<_, _>>()?;
+
+    let (current_offsets, current_elapsed) = time_rounds(ROUNDS, || {
+        list_images
+            .windows(2)
+            .map(|pair| match matcher.find_overlap(&pair[0], &pair[1]) {
+                Ok(overlap_match) => Ok(Some(overlap_match.offset())),
+                Err(Error::ImageNotMatched) => Ok(None),
+                Err(e) => Err(e.into()),
+            })
+            .collect()
+    })?;
+    let (baseline_offsets, baseline_elapsed) = time_rounds(ROUNDS, || {
+        list_mats
+            .windows(2)
+            .map(|pair| baseline_offset(&pair[0], &pair[1]))
+            .collect()
+    })?;
+
+    for (pair_index, (current, baseline)) in
+        current_offsets.iter().zip(&baseline_offsets).enumerate()
+    {
+        println!(
+            "Pair {}-{}: offset {:?}, baseline {:?}",
+            pair_index,
+            pair_index + 1,
+            current,
+            baseline
+        );
+    }
+    assert_eq!(
+        current_offsets, baseline_offsets,
+        "the overlap matcher found other offsets than the baseline search"
+    );
+
+    println!(
+        "Overlap matcher: {}ms, baseline: {}ms ({} pairs, {} rounds)",
+        current_elapsed.as_millis(),
+        baseline_elapsed.as_millis(),
+        current_offsets.len(),
+        ROUNDS
+    );
+
+    Ok(())
+}
+
+fn time_rounds<F>(rounds: u32, mut match_pairs: F) -> anyhow::Result<(Vec<Option<i32>>, Duration)>
+where
+    F: FnMut() -> anyhow::Result<Vec<Option<i32>>>,
+{
+    let start_instant = Instant::now();
+    let mut offsets = Vec::new();
+
+    for _ in 0..rounds {
+        offsets = match_pairs()?;
+    }
+
+    Ok((offsets, start_instant.elapsed()))
+}
+
+// The search before the coarse-to-fine pass and the offset vote: for every partition of the
+// lower image from the top, the first row of the upper image from the bottom scoring above
+// the threshold.
+fn baseline_offset(upper: &Mat, lower: &Mat) -> anyhow::Result<Option<i32>> {
+    const HEIGHT_PARTITION_NUM: i32 = 10;
+    const MATCHING_THRESHOLD: f64 = 0.95;
+
+    let partition_height = lower.rows() / HEIGHT_PARTITION_NUM;
+
+    for partition_num in 0..HEIGHT_PARTITION_NUM {
+        let lower_pos = partition_num * partition_height;
+        let partition = Mat::roi(
+            lower,
+            Rect::new(0, lower_pos, lower.cols(), partition_height),
+        )?;
+
+        for upper_pos in (0..(upper.rows() - partition_height)).rev() {
+            let scanning_area = Mat::roi(
+                upper,
+                Rect::new(0, upper_pos, upper.cols(), partition_height),
+            )?;
+
+            let mut match_result = Mat::default();
+            imgproc::match_template(
+                &scanning_area,
+                &partition,
+                &mut match_result,
+                imgproc::TM_CCOEFF_NORMED,
+                &Mat::default(),
+            )?;
+
+            let mut max_val = 0.0;
+            min_max_loc(
+                &match_result,
+                None,
+                Some(&mut max_val),
+                None,
+                None,
+                &Mat::default(),
+            )?;
+
+            if max_val > MATCHING_THRESHOLD {
+                return Ok(Some(upper_pos - lower_pos));
+            }
+        }
+    }
+
+    Ok(None)
+}
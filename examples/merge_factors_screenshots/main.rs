@@ -20,8 +20,12 @@ fn main() -> anyhow::Result<()> {
         header_trim_mode: None,
         do_merge_close_button: false,
         scaling_threshold_pixels: None,
+        ..ImageConfig::default()
     });
 
+    // Only the trim settings changed, so the stitched factor list is reused.
+    assert!(detail.is_factor_list_stitched());
+
     println!(
         "Close button trimmed details saved in {}.",
         detail.write_to_file(OUT_DIR_PATH, "result_without_close_button.png")?
@@ -31,6 +35,7 @@ fn main() -> anyhow::Result<()> {
         header_trim_mode: Some(HeaderTrimMode::TrimMarginOnly),
         do_merge_close_button: true,
         scaling_threshold_pixels: None,
+        ..ImageConfig::default()
     });
     
     println!(
@@ -42,6 +47,7 @@ fn main() -> anyhow::Result<()> {
         header_trim_mode: Some(HeaderTrimMode::TrimTitleBar),
         do_merge_close_button: true,
         scaling_threshold_pixels: None,
+        ..ImageConfig::default()
     });
 
     println!(
@@ -53,6 +59,7 @@ fn main() -> anyhow::Result<()> {
         header_trim_mode: Some(HeaderTrimMode::TrimMarginOnly),
         do_merge_close_button: false,
         scaling_threshold_pixels: None,
+        ..ImageConfig::default()
     });
     
     println!(
@@ -64,6 +71,7 @@ fn main() -> anyhow::Result<()> {
         header_trim_mode: Some(HeaderTrimMode::TrimTitleBar),
         do_merge_close_button: false,
         scaling_threshold_pixels: None,
+        ..ImageConfig::default()
     });
     
     println!(
@@ -10,9 +10,8 @@ fn main() -> anyhow::Result<()> {
     
     let mut detail =
         image::detail::HorseGirlFullDetailImage::from_path(DIR_PATH, 10, ImageConfig {
-            header_trim_mode: ImageConfig::default().header_trim_mode,
-            do_merge_close_button: ImageConfig::default().do_merge_close_button,
             scaling_threshold_pixels: Some(880000),
+            ..ImageConfig::default()
         })?;
 
     println!(
@@ -24,8 +23,12 @@ fn main() -> anyhow::Result<()> {
         header_trim_mode: None,
         do_merge_close_button: false,
         scaling_threshold_pixels: Some(880000),
+        ..ImageConfig::default()
     });
 
+    // Only the trim settings changed, so the stitched factor list is reused.
+    assert!(detail.is_factor_list_stitched());
+
     println!(
         "Close button trimmed details saved in {}.",
         detail.write_to_file(OUT_DIR_PATH, "result_without_close_button.png")?
@@ -35,6 +38,7 @@ fn main() -> anyhow::Result<()> {
         header_trim_mode: Some(HeaderTrimMode::TrimMarginOnly),
         do_merge_close_button: true,
         scaling_threshold_pixels: Some(880000),
+        ..ImageConfig::default()
     });
     
     println!(
@@ -46,6 +50,7 @@ fn main() -> anyhow::Result<()> {
         header_trim_mode: Some(HeaderTrimMode::TrimTitleBar),
         do_merge_close_button: true,
         scaling_threshold_pixels: Some(880000),
+        ..ImageConfig::default()
     });
 
     println!(
@@ -57,6 +62,7 @@ fn main() -> anyhow::Result<()> {
         header_trim_mode: Some(HeaderTrimMode::TrimMarginOnly),
         do_merge_close_button: false,
         scaling_threshold_pixels: Some(880000),
+        ..ImageConfig::default()
     });
     
     println!(
@@ -68,6 +74,7 @@ fn main() -> anyhow::Result<()> {
         header_trim_mode: Some(HeaderTrimMode::TrimTitleBar),
         do_merge_close_button: false,
         scaling_threshold_pixels: Some(880000),
+        ..ImageConfig::default()
     });
     
     println!(
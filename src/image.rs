@@ -1,7 +1,8 @@
 use std::fmt::Debug;
 use std::fs;
 
-use opencv::core::{Mat, MatTraitConst, MatTraitConstManual};
+use opencv::core::{Mat, MatTraitConst, MatTraitConstManual, MatTraitManual, Scalar};
+use opencv::imgproc;
 use thiserror::Error;
 
 pub mod detail;
@@ -14,6 +15,12 @@ pub enum Error {
     #[error("File {path} not found)")]
     FileNotFound { path: String },
 
+    #[error("Failed to open video {path}")]
+    OpenVideoError { path: String },
+
+    #[error("Failed to decode image from {bytes_len} bytes")]
+    DecodeImageError { bytes_len: usize },
+
     #[error("Not enough images")]
     NotEnoughImageSample,
 
@@ -23,6 +30,31 @@ pub enum Error {
     #[error("Failed to matching images")]
     ImageNotMatched,
 
+    #[error("No overlap between image {upper_index} and image {lower_index}")]
+    NoOverlapBetween {
+        upper_index: usize,
+        lower_index: usize,
+    },
+
+    #[error("Stitched factor list has {} suspicious rows", .warnings.len())]
+    StitchValidationFailed {
+        warnings: Vec<detail::factor::validate::ValidationWarning>,
+    },
+
+    #[error("Overlap is ambiguous between offsets {offsets:?}")]
+    AmbiguousOverlap { offsets: Vec<i32> },
+
+    #[error(
+        "Image size mismatched: expected {expected_width}x{expected_height}, \
+        got {actual_width}x{actual_height}"
+    )]
+    ImageSizeMismatch {
+        expected_width: i32,
+        expected_height: i32,
+        actual_width: i32,
+        actual_height: i32,
+    },
+
     #[error("Cv error: {source}")]
     CvError {
         #[from]
@@ -133,6 +165,38 @@ impl SimpleImage {
     pub fn new(mat: Mat) -> Self {
         Self(mat)
     }
+
+    pub fn from_image(image: &image::DynamicImage) -> Result<Self> {
+        let (buffer, channels, conversion_code) = match image {
+            image::DynamicImage::ImageLuma8(_)
+            | image::DynamicImage::ImageLumaA8(_)
+            | image::DynamicImage::ImageLuma16(_)
+            | image::DynamicImage::ImageLumaA16(_) => {
+                (image.to_luma8().into_raw(), 1, imgproc::COLOR_GRAY2BGR)
+            }
+            i if i.color().has_alpha() => (image.to_rgba8().into_raw(), 4, imgproc::COLOR_RGBA2BGR),
+            _ => (image.to_rgb8().into_raw(), 3, imgproc::COLOR_RGB2BGR),
+        };
+
+        let mat_type = match channels {
+            1 => opencv::core::CV_8UC1,
+            4 => opencv::core::CV_8UC4,
+            _ => opencv::core::CV_8UC3,
+        };
+
+        let mut src_mat = Mat::new_rows_cols_with_default(
+            image.height() as i32,
+            image.width() as i32,
+            mat_type,
+            Scalar::all(0.0),
+        )?;
+        src_mat.data_bytes_mut()?.copy_from_slice(&buffer);
+
+        let mut bgr_mat = Mat::default();
+        imgproc::cvt_color(&src_mat, &mut bgr_mat, conversion_code, 3)?;
+
+        Ok(Self(bgr_mat))
+    }
 }
 
 impl ImageMatrix for SimpleImage {
@@ -170,6 +234,70 @@ impl Rect {
     }
 }
 
+impl Rect {
+    pub fn right(&self) -> i32 {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.y + self.height
+    }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.x < other.right()
+            && other.x < self.right()
+            && self.y < other.bottom()
+            && other.y < self.bottom()
+    }
+
+    pub fn union(&self, other: &Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+
+        Rect::new(
+            x,
+            y,
+            self.right().max(other.right()) - x,
+            self.bottom().max(other.bottom()) - y,
+        )
+    }
+
+    pub fn median<I: IntoIterator<Item = Rect>>(rects: I) -> Option<Rect> {
+        let rects: Vec<Rect> = rects.into_iter().collect();
+        if rects.is_empty() {
+            return None;
+        }
+
+        let median_of = |edge: fn(&Rect) -> i32| {
+            let mut edges: Vec<i32> = rects.iter().map(edge).collect();
+            edges.sort();
+
+            let middle = edges.len() / 2;
+            if edges.len().is_multiple_of(2) {
+                (edges[middle - 1] + edges[middle]) / 2
+            } else {
+                edges[middle]
+            }
+        };
+
+        let x = median_of(|r| r.x);
+        let y = median_of(|r| r.y);
+
+        Some(Rect::new(
+            x,
+            y,
+            median_of(Rect::right) - x,
+            median_of(Rect::bottom) - y,
+        ))
+    }
+}
+
+impl From<opencv::core::Rect> for Rect {
+    fn from(value: opencv::core::Rect) -> Rect {
+        Rect::new(value.x, value.y, value.width, value.height)
+    }
+}
+
 impl From<Rect> for opencv::core::Rect {
     fn from(value: Rect) -> opencv::core::Rect {
         opencv::core::Rect {
@@ -180,3 +308,35 @@ impl From<Rect> for opencv::core::Rect {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edges(rect: Rect) -> (i32, i32, i32, i32) {
+        (rect.x, rect.y, rect.width, rect.height)
+    }
+
+    #[test]
+    fn median_rect_ignores_outlier() {
+        let median = Rect::median([
+            Rect::new(0, 0, 10, 10),
+            Rect::new(2, 4, 10, 10),
+            Rect::new(100, 100, 5, 5),
+        ]);
+
+        assert_eq!(median.map(edges), Some((2, 4, 10, 10)));
+    }
+
+    #[test]
+    fn median_rect_averages_middle_edges_of_even_count() {
+        let median = Rect::median([Rect::new(0, 0, 10, 10), Rect::new(4, 2, 10, 10)]);
+
+        assert_eq!(median.map(edges), Some((2, 1, 10, 10)));
+    }
+
+    #[test]
+    fn median_rect_of_nothing_is_none() {
+        assert!(Rect::median(Vec::new()).is_none());
+    }
+}
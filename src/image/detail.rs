@@ -1,23 +1,36 @@
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
 use opencv::core::{
-    absdiff, in_range, Mat, MatTraitConst, MatTraitConstManual, Point, Rect as cvRect, Scalar, Size,
+    absdiff, count_non_zero, in_range, reduce, Mat, MatTraitConst, MatTraitConstManual, Point,
+    Rect as cvRect, Scalar, Size, CV_32S, REDUCE_SUM,
 };
 use opencv::imgcodecs::{imdecode, imread, IMREAD_COLOR};
 use opencv::imgproc;
 use opencv::types::{VectorOfVectorOfPoint, VectorOfu8};
 
+use factor::align::AlignmentConfig;
+use factor::matcher::{FallbackOverlapMatcher, OverlapMatcher};
+use factor::complete::{Completeness, CompletenessConfig};
+use factor::scroll::{ScrollBarConfig, ScrollBarMode};
+use factor::stitch::{GapPolicy, SeamConfig, StitchReport};
+use factor::validate::ValidationConfig;
 use factor::FactorListPartialImage;
 use footer::FooterImage;
+use group::GroupingConfig;
 use status::StatusImage;
+use video::VideoSamplingConfig;
 
 use crate::image::detail::factor::FactorListImage;
-#[cfg(feature = "image_debug")]
-use crate::image::SimpleImage;
 use crate::image::{CropHeight, CropWidth, CropX, CropY, Error, Result, SizeIdentifiableImage};
-use crate::image::{ImageMatrix, Rect};
+use crate::image::{ImageMatrix, Rect, SimpleImage};
 
 pub mod factor;
 pub mod footer;
+pub mod group;
 pub mod status;
+pub mod video;
+pub mod watch;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum HeaderTrimMode {
@@ -25,11 +38,42 @@ pub enum HeaderTrimMode {
     TrimTitleBar,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub struct ImageConfig {
     pub header_trim_mode: Option<HeaderTrimMode>,
     pub do_merge_close_button: bool,
     pub scaling_threshold_pixels: Option<i32>,
+    pub sort_by_scroll_order: bool,
+    pub overlap_matcher: Arc<dyn OverlapMatcher>,
+    pub gap_policy: GapPolicy,
+    pub alignment: Option<AlignmentConfig>,
+    pub scroll_bar: Option<ScrollBarConfig>,
+    pub scroll_bar_mode: ScrollBarMode,
+    pub seam: SeamConfig,
+    pub validation: Option<ValidationConfig>,
+    pub completeness: Option<CompletenessConfig>,
+}
+
+impl ImageConfig {
+    fn is_same_stitch_config(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.overlap_matcher, &other.overlap_matcher)
+            && self.gap_policy == other.gap_policy
+            && self.alignment == other.alignment
+            && self.scroll_bar == other.scroll_bar
+            && self.scroll_bar_mode == other.scroll_bar_mode
+            && self.seam == other.seam
+            && self.validation == other.validation
+            && self.completeness == other.completeness
+    }
+}
+
+// Shared so configs built from `ImageConfig::default()` compare equal and keep the cache.
+fn default_overlap_matcher() -> Arc<dyn OverlapMatcher> {
+    static DEFAULT_OVERLAP_MATCHER: OnceLock<Arc<dyn OverlapMatcher>> = OnceLock::new();
+
+    DEFAULT_OVERLAP_MATCHER
+        .get_or_init(|| Arc::new(FallbackOverlapMatcher::default()))
+        .clone()
 }
 
 impl Default for ImageConfig {
@@ -38,6 +82,15 @@ impl Default for ImageConfig {
             header_trim_mode: Default::default(),
             do_merge_close_button: true,
             scaling_threshold_pixels: None,
+            sort_by_scroll_order: true,
+            overlap_matcher: default_overlap_matcher(),
+            gap_policy: Default::default(),
+            alignment: Some(AlignmentConfig::default()),
+            scroll_bar: Some(ScrollBarConfig::default()),
+            scroll_bar_mode: Default::default(),
+            seam: Default::default(),
+            validation: Some(ValidationConfig::default()),
+            completeness: Some(CompletenessConfig::default()),
         }
     }
 }
@@ -46,13 +99,15 @@ impl Default for ImageConfig {
 pub struct HorseGirlDetailImage {
     image_mat: Mat,
     factor_list_area: Option<Rect>,
+    detected_list_area: Option<Rect>,
 }
 
 impl HorseGirlDetailImage {
     const BINARY_BRIGHTNESS_THRESHOLD: u8 = 127;
 
-    pub fn from_path(path: &str) -> Result<Self> {
-        let inner = imread(path, IMREAD_COLOR)
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_string_lossy();
+        let inner = imread(&path, IMREAD_COLOR)
             .map_err(|e| Error::LoadImageFromFileError {
                 path: path.to_string(),
                 inner: e,
@@ -70,16 +125,38 @@ impl HorseGirlDetailImage {
         Ok(Self {
             image_mat: inner,
             factor_list_area: Default::default(),
+            detected_list_area: Default::default(),
         })
     }
 
-    pub fn from_image(image: image::DynamicImage) -> Result<Self> {
-        let image = image.into_bytes();
-        let inner = imdecode(&VectorOfu8::from_iter(image), IMREAD_COLOR)?;
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        // `imdecode` throws on an empty buffer instead of returning an empty Mat.
+        if bytes.is_empty() {
+            return Err(Error::DecodeImageError { bytes_len: 0 });
+        }
+
+        let inner = imdecode(&VectorOfu8::from_slice(bytes), IMREAD_COLOR)?;
+
+        if inner.empty() {
+            return Err(Error::DecodeImageError {
+                bytes_len: bytes.len(),
+            });
+        }
 
         Ok(Self {
             image_mat: inner,
             factor_list_area: Default::default(),
+            detected_list_area: Default::default(),
+        })
+    }
+
+    pub fn from_image(image: image::DynamicImage) -> Result<Self> {
+        let inner = SimpleImage::from_image(&image)?;
+
+        Ok(Self {
+            image_mat: inner.0,
+            factor_list_area: Default::default(),
+            detected_list_area: Default::default(),
         })
     }
 
@@ -95,26 +172,199 @@ impl HorseGirlDetailImage {
         )?;
 
         self.image_mat = scaled_mat;
-        if let Some(r) = self.factor_list_area.as_mut() {
+        self.scale_areas(ratio);
+
+        Ok(())
+    }
+
+    pub fn scale_to_width(&mut self, width: i32) -> Result<()> {
+        if self.width() == width {
+            return Ok(());
+        }
+
+        let ratio = width as f64 / self.width() as f64;
+        let height = (self.height() as f64 * ratio).round() as i32;
+
+        let mut scaled_mat = Mat::default();
+        imgproc::resize(
+            &self.image_mat,
+            &mut scaled_mat,
+            Size::new(width, height),
+            0.0,
+            0.0,
+            if ratio < 1.0 {
+                imgproc::INTER_AREA
+            } else {
+                imgproc::INTER_LANCZOS4
+            },
+        )?;
+
+        self.image_mat = scaled_mat;
+        self.scale_areas(ratio);
+
+        Ok(())
+    }
+
+    fn scale_areas(&mut self, ratio: f64) {
+        let scale_rect = |r: &mut Rect| {
             *r = Rect {
                 x: (r.x as f64 * ratio) as i32,
                 y: (r.y as f64 * ratio) as i32,
                 width: (r.width as f64 * ratio) as i32,
                 height: (r.height as f64 * ratio) as i32,
             };
-        }
+        };
 
-        Ok(())
+        if let Some(r) = self.factor_list_area.as_mut() {
+            scale_rect(r);
+        }
+        if let Some(r) = self.detected_list_area.as_mut() {
+            scale_rect(r);
+        }
     }
 
     pub fn get_factor_list_area(&self) -> Result<Rect> {
         self.factor_list_area
             .ok_or(Error::RequiredCalculationsIsNotCompleted {
-                message: "Run first HorseGirlFullDetailImage::calc_children_list_area()"
+                message: "Run first HorseGirlFullDetailImage::calc_children_list_area() \
+                    or HorseGirlDetailImage::calc_factor_list_area_by_layout()"
                     .to_string(),
             })
     }
 
+    pub fn get_detected_list_area(&self) -> Option<Rect> {
+        self.detected_list_area
+    }
+
+    pub fn calc_factor_list_area_by_layout(&mut self) -> Result<Rect> {
+        let list_area_rect = self.detect_factor_list_area_by_layout()?;
+        self.factor_list_area = Some(list_area_rect);
+
+        Ok(list_area_rect)
+    }
+
+    pub fn detect_factor_list_area_by_layout(&self) -> Result<Rect> {
+        const TAB_CENTER_GREEN_RATIO: f64 = 0.6;
+        const TAB_SIDE_GREEN_RATIO: f64 = 0.2;
+        const FOOTER_WHITE_RATIO: f64 = 0.95;
+
+        let mut hsv_image = Mat::default();
+        imgproc::cvt_color(
+            &self.image_mat,
+            &mut hsv_image,
+            imgproc::COLOR_BGR2HSV,
+            self.image_mat.channels(),
+        )?;
+
+        let green_binary_image = Self::green_binary_image(&hsv_image)?;
+
+        let mut white_binary_image = Mat::default();
+        in_range(
+            &hsv_image,
+            &Scalar::new(0.0, 0.0, 253.2, 255.0),
+            &Scalar::new(5.0, 20.0, 255.0, 255.0),
+            &mut white_binary_image,
+        )?;
+
+        // The selected 継承 tab is the only green band that covers the center of the screen
+        // but neither side, unlike the title bar, the status header and the 因子 header bar.
+        let third_width = self.width() / 3;
+        let left_green_ratios = Self::row_white_ratios(&green_binary_image, 0, third_width)?;
+        let center_green_ratios =
+            Self::row_white_ratios(&green_binary_image, third_width, third_width)?;
+        let right_green_ratios =
+            Self::row_white_ratios(&green_binary_image, third_width * 2, third_width)?;
+
+        let is_tab_row = |y: usize| {
+            center_green_ratios[y] >= TAB_CENTER_GREEN_RATIO
+                && left_green_ratios[y] <= TAB_SIDE_GREEN_RATIO
+                && right_green_ratios[y] <= TAB_SIDE_GREEN_RATIO
+        };
+
+        let tab_start_y = (0..center_green_ratios.len())
+            .find(|y| is_tab_row(*y))
+            .ok_or(Error::ImageNotMatched)?;
+        let tab_end_y = (tab_start_y..center_green_ratios.len())
+            .find(|y| !is_tab_row(*y))
+            .ok_or(Error::ImageNotMatched)?;
+        let list_start_y = (tab_end_y + (tab_end_y - tab_start_y) / 2) as i32;
+
+        // The list panel ends where the plain white area around the 閉じる button begins.
+        let scanning_margin = self.width() / 20;
+        let white_ratios = Self::row_white_ratios(
+            &white_binary_image,
+            scanning_margin,
+            self.width() - scanning_margin * 2,
+        )?;
+        let list_end_y = (list_start_y as usize..white_ratios.len())
+            .find(|y| white_ratios[*y] >= FOOTER_WHITE_RATIO)
+            .ok_or(Error::ImageNotMatched)? as i32;
+
+        if list_end_y <= list_start_y {
+            return Err(Error::ImageNotMatched);
+        }
+
+        // The dialog border is plain white on both sides of the list panel.
+        let panel_row = white_binary_image.row(list_end_y - 2)?;
+        let mut panel_row: Vec<u8> = panel_row.data_bytes()?.to_vec();
+        let list_start_x =
+            Self::get_position_black_after_white(&panel_row).ok_or(Error::ImageNotMatched)?;
+        panel_row.reverse();
+        let list_end_x = panel_row.len()
+            - Self::get_position_black_after_white(&panel_row).ok_or(Error::ImageNotMatched)?;
+
+        if list_end_x <= list_start_x {
+            return Err(Error::ImageNotMatched);
+        }
+
+        let list_area_rect = Rect::new(
+            list_start_x as i32,
+            list_start_y,
+            (list_end_x - list_start_x) as i32,
+            list_end_y - list_start_y,
+        );
+
+        #[cfg(feature = "image_debug")]
+        {
+            let mut debug = self.image_mat.clone();
+            imgproc::rectangle(
+                &mut debug,
+                list_area_rect.into(),
+                Scalar::new(0.0, 255.0, 0.0, 255.0),
+                2,
+                imgproc::LINE_8,
+                0,
+            )?;
+            SimpleImage(debug).write_to_file("debug-images", "list-area-layout.png")?;
+        }
+
+        Ok(list_area_rect)
+    }
+
+    // The green of the title bar, the tabs and the 因子 header bar.
+    pub(crate) fn green_binary_image(hsv_image: &Mat) -> Result<Mat> {
+        let mut green_binary_image = Mat::default();
+        in_range(
+            hsv_image,
+            &Scalar::new(25.0, 160.0, 160.0, 255.0),
+            &Scalar::new(60.0, 255.0, 255.0, 255.0),
+            &mut green_binary_image,
+        )?;
+
+        Ok(green_binary_image)
+    }
+
+    pub(crate) fn row_white_ratios(binary_image: &Mat, x: i32, width: i32) -> Result<Vec<f64>> {
+        let binary_image = Mat::roi(binary_image, cvRect::new(x, 0, width, binary_image.rows()))?;
+
+        let mut row_sums = Mat::default();
+        reduce(&binary_image, &mut row_sums, 1, REDUCE_SUM, CV_32S)?;
+
+        (0..row_sums.rows())
+            .map(|y| Ok(*row_sums.at::<i32>(y)? as f64 / (255.0 * width as f64)))
+            .collect()
+    }
+
     pub fn get_left_right_margin(&self) -> Result<i32> {
         let factor_list_area = self.get_factor_list_area()?;
 
@@ -194,13 +444,7 @@ impl HorseGirlDetailImage {
             self.image_mat.channels(),
         )?;
 
-        let mut binary_image = Mat::default();
-        in_range(
-            &hsv_image,
-            &Scalar::new(25.0, 160.0, 160.0, 255.0),
-            &Scalar::new(60.0, 255.0, 255.0, 255.0),
-            &mut binary_image,
-        )?;
+        let binary_image = Self::green_binary_image(&hsv_image)?;
 
         let binary_image = Mat::roi(
             &binary_image,
@@ -391,10 +635,30 @@ impl HorseGirlDetailImage {
         })
     }
 
+    pub fn changed_ratio(&self, other: &Self) -> Result<f64> {
+        if self.image_mat.size()? != other.image_mat.size()? {
+            return Ok(1.0);
+        }
+
+        let diff_threshold_image = self.diff_binary_mat(other)?;
+        let changed_pixels_count = count_non_zero(&diff_threshold_image)?;
+
+        Ok(changed_pixels_count as f64 / self.pixels_count() as f64)
+    }
+
     fn diff_binary_mat(&self, other: &Self) -> Result<Mat> {
         let self_image = &self.image_mat;
         let other_image = &other.image_mat;
 
+        if self.width() != other.width() || self.height() != other.height() {
+            return Err(Error::ImageSizeMismatch {
+                expected_width: self.width(),
+                expected_height: self.height(),
+                actual_width: other.width(),
+                actual_height: other.height(),
+            });
+        }
+
         let mut diff_image = Mat::default();
         absdiff(self_image, other_image, &mut diff_image)?;
 
@@ -439,13 +703,56 @@ impl SizeIdentifiableImage for HorseGirlDetailImage {
 pub struct HorseGirlFullDetailImage {
     images: Vec<HorseGirlDetailImage>,
     config: ImageConfig,
+    skipped_images: Vec<SkippedImage>,
+    input_indices: Vec<usize>,
+    cache: DetailCache,
+}
+
+// Results which only depend on the images, or on the stitch part of the config, so rendering
+// again with another trim mode does not repeat any matching.
+#[derive(Debug, Default)]
+struct DetailCache {
+    stitched_factor_list: OnceLock<(SimpleImage, StitchReport)>,
+    left_right_margin: OnceLock<i32>,
+    top_margin: OnceLock<i32>,
+    top_margin_with_title_bar: OnceLock<i32>,
+    bottom_margin: OnceLock<i32>,
+}
+
+fn get_or_try_init<T, F>(cell: &OnceLock<T>, init: F) -> Result<&T>
+where
+    F: FnOnce() -> Result<T>,
+{
+    if let Some(value) = cell.get() {
+        return Ok(value);
+    }
+
+    let value = init()?;
+
+    Ok(cell.get_or_init(|| value))
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SkipReason {
+    Duplicate { of: usize },
+    NotScrolled { of: usize, changed_ratio: f64 },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SkippedImage {
+    pub index: usize,
+    pub reason: SkipReason,
 }
 
 impl HorseGirlFullDetailImage {
+    pub fn builder(config: ImageConfig) -> HorseGirlFullDetailImageBuilder {
+        HorseGirlFullDetailImageBuilder::new(config)
+    }
+
     pub fn from_path(base_dir_path: &str, images_limit: i32, config: ImageConfig) -> Result<Self> {
         assert!(images_limit > 0, "images_limit must greater then 0");
 
-        let mut images = Vec::new();
+        let mut builder = Self::builder(config);
 
         for i in 1..=images_limit {
             let image_path = format!("{}/{}.png", base_dir_path, i);
@@ -454,55 +761,174 @@ impl HorseGirlFullDetailImage {
             if let Err(Error::FileNotFound { .. }) = image {
                 break;
             }
-            let mut image = image?;
 
-            config.scaling_threshold_pixels.map(|p| {
-                let image_pixels_count = image.pixels_count();
-                if image_pixels_count < p {
-                    return Ok(());
-                }
-                let scale = (p as f64 / image_pixels_count as f64).sqrt();
+            builder.push_image(image?);
+        }
 
-                image.scale_image(scale)
-            });
+        builder.build()
+    }
 
-            images.push(image);
+    pub fn from_paths<I, P>(paths: I, config: ImageConfig) -> Result<Self>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let mut builder = Self::builder(config);
+
+        for path in paths {
+            builder.push_path(path)?;
         }
 
-        if images.len() < 2 {
-            return Err(Error::NotEnoughImageSample);
+        builder.build()
+    }
+
+    pub fn from_images<I>(images: I, config: ImageConfig) -> Result<Self>
+    where
+        I: IntoIterator<Item = HorseGirlDetailImage>,
+    {
+        let mut builder = Self::builder(config);
+
+        for image in images {
+            builder.push_image(image);
         }
 
-        let mut new = Self { images, config };
-        new.calc_children_list_area()?;
+        builder.build()
+    }
 
-        Ok(new)
+    pub fn from_video<P: AsRef<Path>>(
+        video_path: P,
+        sampling_config: &VideoSamplingConfig,
+        config: ImageConfig,
+    ) -> Result<Self> {
+        let images = video::extract_detail_frames(video_path, sampling_config)?;
+
+        Self::from_images(images, config)
+    }
+
+    pub fn group_by_horse<I>(
+        images: I,
+        grouping_config: &GroupingConfig,
+        config: ImageConfig,
+    ) -> Result<Vec<Self>>
+    where
+        I: IntoIterator<Item = HorseGirlDetailImage>,
+    {
+        group::group_by_horse(images, grouping_config, config)
     }
 
     pub fn set_config(&mut self, config: ImageConfig) {
+        if !self.config.is_same_stitch_config(&config) {
+            self.cache.stitched_factor_list = OnceLock::new();
+        }
+
         self.config = config;
     }
 
+    pub fn get_detected_list_areas(&self) -> Vec<Option<Rect>> {
+        self.images
+            .iter()
+            .map(HorseGirlDetailImage::get_detected_list_area)
+            .collect()
+    }
+
+    pub fn get_skipped_images(&self) -> &[SkippedImage] {
+        &self.skipped_images
+    }
+
+    pub fn sort_by_scroll_order(&mut self) -> Result<Vec<usize>> {
+        let order = self.get_factor_list_image()?.infer_order()?;
+
+        let mut images: Vec<Option<HorseGirlDetailImage>> =
+            self.images.drain(..).map(Some).collect();
+        self.images = order
+            .iter()
+            .filter_map(|i| images[*i].take())
+            .collect();
+        self.input_indices = order.iter().map(|i| self.input_indices[*i]).collect();
+        self.cache = Default::default();
+
+        Ok(order)
+    }
+
     pub fn calc_children_list_area(&mut self) -> Result<Rect> {
-        let list_area_rect = self.get_list_area_rect()?;
+        let has_mixed_heights = self
+            .images
+            .windows(2)
+            .any(|pair| pair[0].height() != pair[1].height());
+        if has_mixed_heights {
+            return self.calc_children_list_area_by_layout();
+        }
+
+        let list_area_rect = if self.images.len() < 2 {
+            self.images
+                .first()
+                .ok_or(Error::NotEnoughImageSample)?
+                .detect_factor_list_area_by_layout()?
+        } else {
+            let (list_area_rect, image_rects) = self.get_list_area_rects()?;
+
+            for (image, image_rect) in self.images.iter_mut().zip(image_rects) {
+                image.detected_list_area = image_rect;
+            }
+
+            list_area_rect
+        };
 
         for image in &mut self.images {
             image.factor_list_area = Some(list_area_rect);
         }
+        self.cache = Default::default();
 
         Ok(list_area_rect)
     }
 
+    // Captures from devices with other aspect ratios cannot be diffed against each other, so the
+    // list is found in each of them on its own. The columns are shared so the rows still line up
+    // once stitched.
+    fn calc_children_list_area_by_layout(&mut self) -> Result<Rect> {
+        let layout_rects: Vec<Rect> = self
+            .images
+            .iter()
+            .map(HorseGirlDetailImage::detect_factor_list_area_by_layout)
+            .collect::<Result<_>>()?;
+        let shared_columns =
+            Rect::median(layout_rects.iter().copied()).ok_or(Error::NotEnoughImageSample)?;
+
+        for (image, layout_rect) in self.images.iter_mut().zip(layout_rects) {
+            image.factor_list_area = Some(Rect {
+                x: shared_columns.x,
+                width: shared_columns.width,
+                ..layout_rect
+            });
+            image.detected_list_area = None;
+        }
+        self.cache = Default::default();
+
+        self.images[0].get_factor_list_area()
+    }
+
     pub fn get_left_right_margin(&self) -> Result<i32> {
-        self.images[0].get_left_right_margin()
+        get_or_try_init(&self.cache.left_right_margin, || {
+            self.images[0].get_left_right_margin()
+        })
+        .copied()
     }
 
     pub fn get_top_margin(&self, include_title_bar: bool) -> Result<i32> {
-        self.images[0].get_top_margin(include_title_bar)
+        let cell = if include_title_bar {
+            &self.cache.top_margin_with_title_bar
+        } else {
+            &self.cache.top_margin
+        };
+
+        get_or_try_init(cell, || self.images[0].get_top_margin(include_title_bar)).copied()
     }
 
     pub fn get_bottom_margin(&self) -> Result<i32> {
-        self.images[0].get_bottom_margin()
+        get_or_try_init(&self.cache.bottom_margin, || {
+            self.images[0].get_bottom_margin()
+        })
+        .copied()
     }
 
     pub fn get_status_image(&self) -> Result<StatusImage> {
@@ -517,14 +943,116 @@ impl HorseGirlFullDetailImage {
         self.images[0].get_footer_image()
     }
 
-    fn get_list_area_rect(&self) -> Result<Rect> {
+    pub fn is_factor_list_stitched(&self) -> bool {
+        self.cache.stitched_factor_list.get().is_some()
+    }
+
+    // Rows in the report are rows of the image `convert_to_mat` writes, and rows of the
+    // screenshots the segments were taken from.
+    pub fn get_stitch_report(&self) -> Result<StitchReport> {
+        let mut report = self.get_stitched_factor_list()?.1.clone();
+        report.shift_output_y(self.get_factor_list_output_y()?);
+
+        Ok(report)
+    }
+
+    fn get_factor_list_output_y(&self) -> Result<i32> {
+        let status_height = self.images[0].get_factor_list_area()?.y;
+        let margin_top = match self.config.header_trim_mode {
+            Some(trim_mode) => self.get_top_margin(trim_mode == HeaderTrimMode::TrimTitleBar)?,
+            None => 0,
+        };
+
+        Ok(status_height - margin_top)
+    }
+
+    pub fn get_completeness(&self) -> Result<Option<Completeness>> {
+        Ok(self.get_stitched_factor_list()?.1.completeness)
+    }
+
+    fn get_stitched_factor_list(&self) -> Result<&(SimpleImage, StitchReport)> {
+        get_or_try_init(&self.cache.stitched_factor_list, || {
+            // The factor list only knows the images left after skipping and sorting, while the
+            // caller knows the positions the images were given in.
+            let input_index = |index: usize| self.input_indices[index];
+
+            let stitched = self.get_factor_list_image()?.stitch();
+            let (merged_image, mut report) = stitched.map_err(|e| match e {
+                Error::NoOverlapBetween {
+                    upper_index,
+                    lower_index,
+                } => Error::NoOverlapBetween {
+                    upper_index: input_index(upper_index),
+                    lower_index: input_index(lower_index),
+                },
+                e => e,
+            })?;
+            for segment in &mut report.provenance {
+                segment.source_y += self.images[segment.source_index].get_factor_list_area()?.y;
+            }
+            report.map_indices(input_index);
+
+            Ok((merged_image, report))
+        })
+    }
+
+    fn get_list_area_rects(&self) -> Result<(Rect, Vec<Option<Rect>>)> {
+        let pair_diff_rects: Vec<Vec<Rect>> = self
+            .images
+            .windows(2)
+            .map(|pair| Self::get_diff_rects(&pair[0], &pair[1]))
+            .collect::<Result<_>>()?;
+
+        // Sparkle animations and similar noise only show up in a single pair, while the list
+        // keeps changing at the same place in every pair.
+        let pair_rects: Vec<Option<Rect>> = pair_diff_rects
+            .iter()
+            .enumerate()
+            .map(|(pair_index, diff_rects)| {
+                diff_rects
+                    .iter()
+                    .filter(|rect| {
+                        pair_diff_rects.len() < 2
+                            || pair_diff_rects
+                                .iter()
+                                .enumerate()
+                                .filter(|(other_index, _)| *other_index != pair_index)
+                                .any(|(_, other_rects)| {
+                                    other_rects.iter().any(|other| rect.intersects(other))
+                                })
+                    })
+                    .copied()
+                    .reduce(|covered, rect| covered.union(&rect))
+            })
+            .collect();
+
+        // Toasts stay on screen across a few captures, so the edges are combined by median to
+        // keep a single odd pair from stretching the list area.
+        let list_area_rect = Rect::median(pair_rects.iter().flatten().copied())
+            .ok_or(Error::ImageNotMatched)?;
+
+        let image_rects = (0..self.images.len())
+            .map(|i| {
+                let adjacent_pair_rects = [
+                    i.checked_sub(1).and_then(|p| pair_rects.get(p)).copied().flatten(),
+                    pair_rects.get(i).copied().flatten(),
+                ];
+
+                Rect::median(adjacent_pair_rects.into_iter().flatten())
+            })
+            .collect();
+
+        Ok((list_area_rect, image_rects))
+    }
+
+    fn get_diff_rects(
+        first_image: &HorseGirlDetailImage,
+        second_image: &HorseGirlDetailImage,
+    ) -> Result<Vec<Rect>> {
         const DIFF_THRESHOLD_PIXELS_COUNT: i32 = 10;
         const SCANNING_AREA_START_PARTITION_NUM: i32 = 8;
         const SCANNING_AREA_END_PARTITION_NUM: i32 = 16;
 
-        let first_image = &self.images[0];
-        let second_image = &self.images[1];
-
         let scanning_area_start_y = first_image.height() / SCANNING_AREA_START_PARTITION_NUM;
         let scanning_area_end_y =
             first_image.height() - (first_image.height() / SCANNING_AREA_END_PARTITION_NUM);
@@ -539,7 +1067,7 @@ impl HorseGirlFullDetailImage {
                 imgproc::COLOR_GRAY2BGR,
                 first_image.image_mat.channels(),
             )?;
-            
+
             imgproc::rectangle(
                 &mut debug,
                 cvRect::new(
@@ -553,7 +1081,14 @@ impl HorseGirlFullDetailImage {
                 imgproc::LINE_8,
                 0,
             )?;
-            SimpleImage(debug).write_to_file("debug-images", "list-area-diff.png")?;
+            SimpleImage(debug).write_to_file(
+                "debug-images",
+                format!(
+                    "list-area-diff-{}.png",
+                    chrono::Local::now().timestamp_millis()
+                )
+                .as_str(),
+            )?;
         }
 
         let mut diff_contours = VectorOfVectorOfPoint::new();
@@ -565,7 +1100,7 @@ impl HorseGirlFullDetailImage {
             Point::new(0, 0),
         )?;
 
-        let mut all_diff_covered_rect = Rect::default();
+        let mut diff_rects = Vec::new();
 
         for contour in diff_contours.iter() {
             let rect = imgproc::bounding_rect(&contour)?;
@@ -580,45 +1115,149 @@ impl HorseGirlFullDetailImage {
                 continue;
             }
 
-            if all_diff_covered_rect.x == Default::default() {
-                all_diff_covered_rect.x = rect.x;
-            }
-            if all_diff_covered_rect.y == Default::default() {
-                all_diff_covered_rect.y = rect.y;
-            }
-            if all_diff_covered_rect.width == Default::default() {
-                all_diff_covered_rect.width = rect.width;
-            }
-            if all_diff_covered_rect.height == Default::default() {
-                all_diff_covered_rect.height = rect.height;
-            }
+            diff_rects.push(Rect::from(rect));
+        }
+
+        Ok(diff_rects)
+    }
+}
+
+#[derive(Debug)]
+pub struct HorseGirlFullDetailImageBuilder {
+    images: Vec<HorseGirlDetailImage>,
+    config: ImageConfig,
+}
+
+impl HorseGirlFullDetailImageBuilder {
+    pub fn new(config: ImageConfig) -> Self {
+        Self {
+            images: Vec::new(),
+            config,
+        }
+    }
+
+    pub fn push_image(&mut self, image: HorseGirlDetailImage) -> &mut Self {
+        self.images.push(image);
+        self
+    }
+
+    pub fn push_path<P: AsRef<Path>>(&mut self, path: P) -> Result<&mut Self> {
+        let image = HorseGirlDetailImage::from_path(path)?;
+
+        Ok(self.push_image(image))
+    }
+
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    pub fn build(mut self) -> Result<HorseGirlFullDetailImage> {
+        Self::normalize_images(&mut self.images, &self.config)?;
+        let images_count = self.images.len();
+        let (images, skipped_images) = Self::skip_duplicated_images(self.images)?;
 
-            if rect.x < all_diff_covered_rect.x {
-                all_diff_covered_rect.width += all_diff_covered_rect.x - rect.x;
-                all_diff_covered_rect.x = rect.x;
+        if images.is_empty() {
+            return Err(Error::NotEnoughImageSample);
+        }
+
+        let input_indices = (0..images_count)
+            .filter(|i| skipped_images.iter().all(|skipped| skipped.index != *i))
+            .collect();
+
+        let mut new = HorseGirlFullDetailImage {
+            images,
+            config: self.config,
+            skipped_images,
+            input_indices,
+            cache: Default::default(),
+        };
+        new.calc_children_list_area()?;
+
+        // Captures that cannot all be placed are left in the given order, where the gap policy
+        // decides what happens to them.
+        if new.config.sort_by_scroll_order && new.images.len() > 1 {
+            match new.sort_by_scroll_order() {
+                Ok(_) | Err(Error::ImageNotMatched) => {}
+                Err(e) => return Err(e),
             }
-            if rect.y < all_diff_covered_rect.y {
-                all_diff_covered_rect.height += all_diff_covered_rect.y - rect.y;
-                all_diff_covered_rect.y = rect.y;
+        }
+
+        Ok(new)
+    }
+
+    fn normalize_images(images: &mut [HorseGirlDetailImage], config: &ImageConfig) -> Result<()> {
+        if let Some(p) = config.scaling_threshold_pixels {
+            for image in images.iter_mut() {
+                let image_pixels_count = image.pixels_count();
+                if image_pixels_count < p {
+                    continue;
+                }
+                let scale = (p as f64 / image_pixels_count as f64).sqrt();
+
+                image.scale_image(scale)?;
             }
-            if (rect.x + rect.width) > (all_diff_covered_rect.x + all_diff_covered_rect.width) {
-                all_diff_covered_rect.width = rect.x + rect.width - all_diff_covered_rect.x;
+        }
+
+        let Some(common_width) = images.iter().map(SizeIdentifiableImage::width).min() else {
+            return Ok(());
+        };
+
+        for image in images.iter_mut() {
+            image.scale_to_width(common_width)?;
+        }
+
+        Ok(())
+    }
+
+    fn skip_duplicated_images(
+        images: Vec<HorseGirlDetailImage>,
+    ) -> Result<(Vec<HorseGirlDetailImage>, Vec<SkippedImage>)> {
+        const NOT_SCROLLED_CHANGED_RATIO: f64 = 0.002;
+
+        let mut kept_images: Vec<(usize, HorseGirlDetailImage)> = Vec::new();
+        let mut skipped_images = Vec::new();
+
+        for (index, image) in images.into_iter().enumerate() {
+            let mut skip_reason = None;
+
+            for (kept_index, kept_image) in &kept_images {
+                let changed_ratio = image.changed_ratio(kept_image)?;
+
+                if changed_ratio == 0.0 {
+                    skip_reason = Some(SkipReason::Duplicate { of: *kept_index });
+                    break;
+                }
+                if changed_ratio < NOT_SCROLLED_CHANGED_RATIO {
+                    skip_reason = Some(SkipReason::NotScrolled {
+                        of: *kept_index,
+                        changed_ratio,
+                    });
+                    break;
+                }
             }
-            if (rect.y + rect.height) > (all_diff_covered_rect.y + all_diff_covered_rect.height) {
-                all_diff_covered_rect.height = rect.y + rect.height - all_diff_covered_rect.y;
+
+            match skip_reason {
+                Some(reason) => skipped_images.push(SkippedImage { index, reason }),
+                None => kept_images.push((index, image)),
             }
         }
 
-        Ok(all_diff_covered_rect)
+        let kept_images = kept_images.into_iter().map(|(_, image)| image).collect();
+
+        Ok((kept_images, skipped_images))
     }
 }
 
 impl ImageMatrix for HorseGirlFullDetailImage {
     fn convert_to_mat(&self) -> Result<Mat> {
         let status_image = self.get_status_image()?;
-        let factor_image = self.get_factor_list_image()?;
+        let (factor_image, _) = self.get_stitched_factor_list()?;
 
-        let mut merged_image = status_image.get_merged_below(&factor_image)?;
+        let mut merged_image = status_image.get_merged_below(factor_image)?;
 
         if self.config.do_merge_close_button {
             let footer_image = self.get_footer_image()?;
@@ -1,15 +1,26 @@
-#[cfg(feature = "image_debug")]
-use opencv::core::Scalar;
-use opencv::core::{min_max_loc, Mat, MatTraitConst};
-use opencv::imgproc;
+use std::sync::Arc;
+
+use opencv::core::{Mat, MatTraitConst};
+
+use align::AlignmentConfig;
+use complete::{Completeness, CompletenessConfig};
+use matcher::{FallbackOverlapMatcher, MatchedPoint, OffsetHint, OverlapMatch, OverlapMatcher};
+use scroll::{ScrollBarConfig, ScrollBarMode, ScrollBarStyle, ScrollBarThumb};
+use stitch::{GapPolicy, PairLink, SeamConfig, StitchReport};
+use validate::ValidationConfig;
 
 use crate::image::detail::{HorseGirlDetailImage, HorseGirlFullDetailImage};
 use crate::image::{
     CropHeight, CropY, Error, ImageMatrix, Rect, Result, SimpleImage, SizeIdentifiableImage,
 };
 
-const HEIGHT_PARTITION_NUM: i32 = 10;
-const MATCHING_THRESHOLD: f64 = 0.95;
+pub mod align;
+pub mod complete;
+pub mod matcher;
+pub mod scroll;
+pub mod stitch;
+pub mod stream;
+pub mod validate;
 
 #[derive(Debug, Clone)]
 pub struct FactorListPartialImage {
@@ -17,9 +28,6 @@ pub struct FactorListPartialImage {
     factor_list_area: Rect,
 }
 
-#[derive(Debug, Copy, Clone)]
-struct MatchedPoint(i32, i32);
-
 impl FactorListPartialImage {
     pub fn from_detail(src: &HorseGirlDetailImage) -> Result<Self> {
         let mut factor_list_area = src.get_factor_list_area()?;
@@ -43,15 +51,27 @@ impl FactorListPartialImage {
     }
 
     pub fn merge_below(&mut self, other: &Self) -> Result<()> {
-        self.image_mat = self.get_merged_below(other)?.0;
+        self.merge_below_with(other, &FallbackOverlapMatcher::default())
+    }
+
+    pub fn merge_below_with(&mut self, other: &Self, matcher: &dyn OverlapMatcher) -> Result<()> {
+        self.image_mat = self.get_merged_below_with(other, matcher)?.0;
         self.factor_list_area.height = self.height();
 
         Ok(())
     }
 
     pub fn get_merged_below(&self, other: &Self) -> Result<SimpleImage> {
+        self.get_merged_below_with(other, &FallbackOverlapMatcher::default())
+    }
+
+    pub fn get_merged_below_with(
+        &self,
+        other: &Self,
+        matcher: &dyn OverlapMatcher,
+    ) -> Result<SimpleImage> {
         let MatchedPoint(self_image_matching, other_image_matching) =
-            self.detect_match_area(other)?;
+            self.detect_match_area(other, matcher, None)?.matched_point;
 
         let trimmed_self_image =
             self.vertical_crop_image(CropY(0), CropHeight(self_image_matching))?;
@@ -71,153 +91,122 @@ impl FactorListPartialImage {
         Ok(roi)
     }
 
-    fn detect_match_area(&self, other: &FactorListPartialImage) -> Result<MatchedPoint> {
+    pub fn detect_scroll_bar(&self, config: &ScrollBarConfig) -> Result<Option<ScrollBarThumb>> {
+        scroll::detect_thumb(&self.image_mat, &self.factor_list_area, config)
+    }
+
+    fn detect_match_area(
+        &self,
+        other: &FactorListPartialImage,
+        matcher: &dyn OverlapMatcher,
+        hint: Option<OffsetHint>,
+    ) -> Result<OverlapMatch> {
         let self_matching_roi = self.get_list_area_roi()?;
         let other_matching_roi = other.get_list_area_roi()?;
 
-        let partition_height = other_matching_roi.height() / HEIGHT_PARTITION_NUM;
+        match hint {
+            Some(hint) => matcher.find_overlap_near(&self_matching_roi, &other_matching_roi, hint),
+            None => matcher.find_overlap(&self_matching_roi, &other_matching_roi),
+        }
+    }
+
+    // A full-size ROI is a new header over the same pixels, which is cheap to move to a worker.
+    #[cfg(feature = "parallel")]
+    fn share_pixels(&self) -> Result<Self> {
+        let full_area = Rect::new(0, 0, self.image_mat.cols(), self.image_mat.rows());
 
-        for partition_num in 0..HEIGHT_PARTITION_NUM {
-            let other_scanning_pos = partition_num * partition_height;
-            let other_scanning_roi = other_matching_roi
-                .vertical_crop_image(CropY(other_scanning_pos), CropHeight(partition_height))?;
-            
-            #[cfg(feature = "image_debug")]
-            {
-                let mut debug = other.image_mat.clone();
-                imgproc::rectangle(
-                    &mut debug,
-                    Rect::new(
-                        other.factor_list_area.x,
-                        other_scanning_pos,
-                        other.factor_list_area.width,
-                        partition_height,
-                    )
-                        .into(),
-                    Scalar::new(0.0, 0.0, 255.0, 255.0),
-                    2,
-                    imgproc::LINE_8,
-                    0,
-                )?;
-                SimpleImage(debug).write_to_file(
-                    "debug-images",
-                    format!(
-                        "other-scanning-{}.png",
-                        chrono::Local::now().timestamp_millis()
-                    )
-                        .as_str(),
-                )?;
-            }
+        Ok(Self {
+            image_mat: Mat::roi(&self.image_mat, full_area.into())?,
+            factor_list_area: self.factor_list_area,
+        })
+    }
 
-            for self_scanning_pos in (0..(self_matching_roi.height() - partition_height)).rev() {
-                let self_scanning_roi = self_matching_roi
-                    .vertical_crop_image(CropY(self_scanning_pos), CropHeight(partition_height))?;
-                
-                #[cfg(feature = "image_debug")]
-                {
-                    let mut debug = self.image_mat.clone();
-                    imgproc::rectangle(
-                        &mut debug,
-                        Rect::new(
-                            self.factor_list_area.x,
-                            self_scanning_pos,
-                            self.factor_list_area.width,
-                            partition_height,
-                        )
-                            .into(),
-                        Scalar::new(0.0, 0.0, 255.0, 255.0),
-                        2,
-                        imgproc::LINE_8,
-                        0,
-                    )?;
-                    SimpleImage(debug).write_to_file(
-                        "debug-images",
-                        format!(
-                            "self-scanning-{}.png",
-                            chrono::Local::now().timestamp_millis()
-                        )
-                            .as_str(),
-                    )?;
-                }
+    fn scroll_hint(
+        &self,
+        other: &FactorListPartialImage,
+        config: &ScrollBarConfig,
+    ) -> Result<Option<OffsetHint>> {
+        let (Some(self_thumb), Some(other_thumb)) =
+            (self.detect_scroll_bar(config)?, other.detect_scroll_bar(config)?)
+        else {
+            return Ok(None);
+        };
 
-                let mut match_result = Mat::default();
-                imgproc::match_template(
-                    &self_scanning_roi.0,
-                    &other_scanning_roi.0,
-                    &mut match_result,
-                    imgproc::TM_CCOEFF_NORMED,
-                    &Mat::default(),
-                )?;
-
-                let mut max_val = 0.0;
-
-                min_max_loc(
-                    &match_result,
-                    None,
-                    Some(&mut max_val),
-                    None,
-                    None,
-                    &Mat::default(),
-                )?;
-
-                if max_val > MATCHING_THRESHOLD {
-                    #[cfg(feature = "image_debug")]
-                    {
-                        let mut debug = self.image_mat.clone();
-                        imgproc::rectangle(
-                            &mut debug,
-                            Rect::new(
-                                self.factor_list_area.x,
-                                self_scanning_pos,
-                                self.factor_list_area.width,
-                                partition_height,
-                            )
-                            .into(),
-                            Scalar::new(0.0, 0.0, 255.0, 255.0),
-                            2,
-                            imgproc::LINE_8,
-                            0,
-                        )?;
-                        SimpleImage(debug).write_to_file(
-                            "debug-images",
-                            format!(
-                                "self-matched-{}.png",
-                                chrono::Local::now().timestamp_millis()
-                            )
-                            .as_str(),
-                        )?;
-
-                        let mut debug = other.image_mat.clone();
-                        imgproc::rectangle(
-                            &mut debug,
-                            Rect::new(
-                                other.factor_list_area.x,
-                                other_scanning_pos,
-                                other.factor_list_area.width,
-                                partition_height,
-                            )
-                            .into(),
-                            Scalar::new(0.0, 0.0, 255.0, 255.0),
-                            2,
-                            imgproc::LINE_8,
-                            0,
-                        )?;
-                        SimpleImage(debug).write_to_file(
-                            "debug-images",
-                            format!(
-                                "other-matched-{}.png",
-                                chrono::Local::now().timestamp_millis()
-                            )
-                            .as_str(),
-                        )?;
-                    }
+        Ok(Some(scroll::offset_hint(&self_thumb, &other_thumb, config)))
+    }
 
-                    return Ok(MatchedPoint(self_scanning_pos, other_scanning_pos));
-                }
+    // Used when the content does not match but the scroll bars say the captures overlap. When
+    // they say the captures do not overlap either, the pair is a real gap.
+    fn estimate_match(
+        &self,
+        other: &FactorListPartialImage,
+        hint: OffsetHint,
+    ) -> Result<OverlapMatch> {
+        let overlap_start = hint.offset.max(0);
+        let overlap_end = self.height().min(other.height() + hint.offset);
+        if overlap_end - overlap_start <= hint.tolerance * 2 {
+            return Err(Error::ImageNotMatched);
+        }
+
+        let upper_matching = (overlap_start + overlap_end) / 2;
+
+        Ok(OverlapMatch {
+            matched_point: MatchedPoint(upper_matching, upper_matching - hint.offset),
+            score: 0.0,
+            drift: Default::default(),
+            estimated: true,
+        })
+    }
+
+    fn validate_merged(
+        &self,
+        merged_image: &SimpleImage,
+        report: &mut StitchReport,
+        validation: &ValidationConfig,
+    ) -> Result<()> {
+        let list_x = self.factor_list_area.x..self.factor_list_area.right();
+        report.warnings = validate::validate(merged_image, list_x, report, validation)?;
+
+        if validation.fail_on_warning && !report.warnings.is_empty() {
+            return Err(Error::StitchValidationFailed {
+                warnings: report.warnings.clone(),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn detect_aligned_match(
+        &self,
+        other: &FactorListPartialImage,
+        matcher: &dyn OverlapMatcher,
+        alignment: Option<&AlignmentConfig>,
+        scroll_bar: Option<&ScrollBarConfig>,
+    ) -> Result<OverlapMatch> {
+        let hint = match scroll_bar {
+            Some(scroll_bar) => self.scroll_hint(other, scroll_bar)?,
+            None => None,
+        };
+
+        let mut overlap_match = match (self.detect_match_area(other, matcher, hint), hint) {
+            (Err(Error::ImageNotMatched), Some(hint))
+                if scroll_bar.is_some_and(|scroll_bar| scroll_bar.fallback_to_estimate) =>
+            {
+                self.estimate_match(other, hint)?
             }
+            (matched, _) => matched?,
+        };
+
+        if let Some(alignment) = alignment {
+            overlap_match.drift = align::estimate_drift(
+                &self.get_list_area_roi()?,
+                &other.get_list_area_roi()?,
+                overlap_match.matched_point,
+                alignment,
+            )?;
         }
 
-        Err(Error::ImageNotMatched)
+        Ok(overlap_match)
     }
 }
 
@@ -240,6 +229,14 @@ impl SizeIdentifiableImage for FactorListPartialImage {
 #[derive(Debug)]
 pub struct FactorListImage {
     images: Vec<FactorListPartialImage>,
+    overlap_matcher: Arc<dyn OverlapMatcher>,
+    gap_policy: GapPolicy,
+    alignment: Option<AlignmentConfig>,
+    scroll_bar: Option<ScrollBarConfig>,
+    scroll_bar_mode: ScrollBarMode,
+    seam: SeamConfig,
+    validation: Option<ValidationConfig>,
+    completeness: Option<CompletenessConfig>,
 }
 
 impl FactorListImage {
@@ -250,28 +247,498 @@ impl FactorListImage {
             .map(FactorListPartialImage::from_detail)
             .collect();
 
-        Ok(Self { images: images? })
+        Ok(Self {
+            images: images?,
+            overlap_matcher: src.config.overlap_matcher.clone(),
+            gap_policy: src.config.gap_policy,
+            alignment: src.config.alignment,
+            scroll_bar: src.config.scroll_bar,
+            scroll_bar_mode: src.config.scroll_bar_mode,
+            seam: src.config.seam,
+            validation: src.config.validation,
+            completeness: src.config.completeness,
+        })
+    }
+
+    pub fn get_images(&self) -> &[FactorListPartialImage] {
+        &self.images
+    }
+
+    pub fn set_gap_policy(&mut self, gap_policy: GapPolicy) {
+        self.gap_policy = gap_policy;
+    }
+
+    pub fn set_alignment(&mut self, alignment: Option<AlignmentConfig>) {
+        self.alignment = alignment;
+    }
+
+    pub fn set_scroll_bar(&mut self, scroll_bar: Option<ScrollBarConfig>) {
+        self.scroll_bar = scroll_bar;
+    }
+
+    pub fn detect_scroll_bars(&self) -> Result<Vec<Option<ScrollBarThumb>>> {
+        let config = self.scroll_bar.unwrap_or_default();
+
+        self.images
+            .iter()
+            .map(|image| image.detect_scroll_bar(&config))
+            .collect()
+    }
+
+    pub fn set_scroll_bar_mode(&mut self, scroll_bar_mode: ScrollBarMode) {
+        self.scroll_bar_mode = scroll_bar_mode;
+    }
+
+    pub fn set_seam(&mut self, seam: SeamConfig) {
+        self.seam = seam;
+    }
+
+    pub fn set_validation(&mut self, validation: Option<ValidationConfig>) {
+        self.validation = validation;
+    }
+
+    pub fn set_completeness(&mut self, completeness: Option<CompletenessConfig>) {
+        self.completeness = completeness;
+    }
+
+    pub fn check_completeness(&self) -> Result<Completeness> {
+        let (Some(first), Some(last)) = (self.images.first(), self.images.last()) else {
+            return Err(Error::NotEnoughImageSample);
+        };
+
+        complete::check(
+            first,
+            last,
+            &self.scroll_bar.unwrap_or_default(),
+            &self.completeness.unwrap_or_default(),
+        )
+    }
+
+    pub fn set_overlap_matcher(&mut self, matcher: Arc<dyn OverlapMatcher>) {
+        self.overlap_matcher = matcher;
     }
 
     pub fn push(&mut self, image: FactorListPartialImage) {
         self.images.push(image)
     }
+
+    pub fn infer_order(&self) -> Result<Vec<usize>> {
+        let images_count = self.images.len();
+        if images_count == 0 {
+            return Ok(Vec::new());
+        }
+
+        let matcher = self
+            .overlap_matcher
+            .primary()
+            .unwrap_or(&*self.overlap_matcher);
+
+        let pairs: Vec<(usize, usize)> = (0..images_count)
+            .flat_map(|i| ((i + 1)..images_count).map(move |j| (i, j)))
+            .collect();
+        let pair_matches = self.match_pairs(&pairs, |upper, lower| {
+            upper.detect_match_area(lower, matcher, None)
+        });
+
+        let mut edges: Vec<(f64, usize, usize, i32)> = Vec::new();
+        for ((i, j), matched) in pairs.into_iter().zip(pair_matches) {
+            match matched {
+                Ok(overlap_match) => {
+                    edges.push((overlap_match.score, i, j, overlap_match.offset()));
+                }
+                Err(Error::ImageNotMatched) | Err(Error::AmbiguousOverlap { .. }) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        // Only the images placed together with the first one have a known position.
+        let placed = Self::spanning_positions(images_count, edges);
+        let mut scroll_positions: Vec<Option<i32>> = placed
+            .iter()
+            .map(|(group, position)| (*group == placed[0].0).then_some(*position))
+            .collect();
+
+        // Captures the content could not place are put where their scroll bar says, as long as
+        // the first capture has one to measure from.
+        if scroll_positions.iter().any(Option::is_none) {
+            if let Some(scroll_bar) = &self.scroll_bar {
+                let scroll_estimates: Vec<Option<i32>> = self
+                    .images
+                    .iter()
+                    .map(|image| {
+                        Ok(image
+                            .detect_scroll_bar(scroll_bar)?
+                            .map(|thumb| thumb.scroll_offset()))
+                    })
+                    .collect::<Result<_>>()?;
+
+                if let Some(first_estimate) = scroll_estimates[0] {
+                    for (position, estimate) in scroll_positions.iter_mut().zip(&scroll_estimates) {
+                        if position.is_none() {
+                            *position = estimate.map(|estimate| estimate - first_estimate);
+                        }
+                    }
+                }
+            }
+        }
+
+        let scroll_positions: Vec<i32> = scroll_positions
+            .into_iter()
+            .collect::<Option<Vec<i32>>>()
+            .ok_or(Error::ImageNotMatched)?;
+
+        let mut order: Vec<usize> = (0..images_count).collect();
+        order.sort_by_key(|i| scroll_positions[*i]);
+
+        Ok(order)
+    }
+
+    // Places the images along the strongest matches first, like a maximum spanning tree. A
+    // weaker match between images which are already placed relative to each other is either
+    // redundant or a false match of the repeated card layout, so it is dropped. Returns the
+    // group and the scroll position within the group of every image.
+    fn spanning_positions(
+        images_count: usize,
+        mut edges: Vec<(f64, usize, usize, i32)>,
+    ) -> Vec<(usize, i32)> {
+        edges.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let mut positions: Vec<(usize, i32)> = (0..images_count).map(|i| (i, 0)).collect();
+        for (_, upper, lower, offset) in edges {
+            let (upper_group, upper_position) = positions[upper];
+            let (lower_group, lower_position) = positions[lower];
+            if upper_group == lower_group {
+                continue;
+            }
+
+            let shift = upper_position + offset - lower_position;
+            for (group, position) in positions.iter_mut() {
+                if *group == lower_group {
+                    *group = upper_group;
+                    *position += shift;
+                }
+            }
+        }
+
+        positions
+    }
+
+    pub fn stitch(&self) -> Result<(SimpleImage, StitchReport)> {
+        if self.images.is_empty() {
+            return Err(Error::NotEnoughImageSample);
+        }
+
+        let pair_matches = self.detect_pair_matches();
+        let (chain, links, skipped_indices) =
+            link_images(self.images.len(), self.gap_policy, pair_matches, |upper, lower| {
+                self.match_pair(upper, lower)
+            })?;
+
+        let (mut merged_image, mut report) =
+            stitch::compose(&self.images, &chain, &links, &self.seam)?;
+        report.input_order = (0..self.images.len()).collect();
+        report.skipped_indices = skipped_indices;
+
+        if self.scroll_bar_mode != ScrollBarMode::Keep {
+            if let Some(style) = self.scroll_bar_style()? {
+                style.repaint(&mut merged_image.0, self.scroll_bar_mode)?;
+            }
+        }
+
+        if let Some(completeness) = &self.completeness {
+            let first = &self.images[chain[0]];
+            let last = &self.images[chain[chain.len() - 1]];
+            report.completeness = Some(complete::check(
+                first,
+                last,
+                &self.scroll_bar.unwrap_or_default(),
+                completeness,
+            )?);
+        }
+
+        if let Some(validation) = &self.validation {
+            self.images[0].validate_merged(&merged_image, &mut report, validation)?;
+        }
+
+        Ok((merged_image, report))
+    }
+
+    pub fn get_stitch_report(&self) -> Result<StitchReport> {
+        Ok(self.stitch()?.1)
+    }
+
+    fn scroll_bar_style(&self) -> Result<Option<ScrollBarStyle>> {
+        let config = self.scroll_bar.unwrap_or_default();
+        let mut styles = Vec::new();
+
+        for image in &self.images {
+            let Some(thumb) = image.detect_scroll_bar(&config)? else {
+                continue;
+            };
+
+            styles.push(ScrollBarStyle::sample(&image.image_mat, &thumb)?);
+        }
+
+        Ok(ScrollBarStyle::median(&styles))
+    }
+
+    fn detect_pair_matches(&self) -> Vec<Result<OverlapMatch>> {
+        let pairs: Vec<(usize, usize)> = (1..self.images.len()).map(|i| (i - 1, i)).collect();
+        let matcher = &*self.overlap_matcher;
+        let alignment = self.alignment;
+        let scroll_bar = self.scroll_bar;
+
+        self.match_pairs(&pairs, |upper, lower| {
+            upper.detect_aligned_match(lower, matcher, alignment.as_ref(), scroll_bar.as_ref())
+        })
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    fn match_pairs<F>(&self, pairs: &[(usize, usize)], match_pair: F) -> Vec<Result<OverlapMatch>>
+    where
+        F: Fn(&FactorListPartialImage, &FactorListPartialImage) -> Result<OverlapMatch> + Sync,
+    {
+        pairs
+            .iter()
+            .map(|(upper, lower)| match_pair(&self.images[*upper], &self.images[*lower]))
+            .collect()
+    }
+
+    // Mats cannot be shared between threads, so every pair gets its own headers over the
+    // pixels of the images instead of copies of them.
+    #[cfg(feature = "parallel")]
+    fn match_pairs<F>(&self, pairs: &[(usize, usize)], match_pair: F) -> Vec<Result<OverlapMatch>>
+    where
+        F: Fn(&FactorListPartialImage, &FactorListPartialImage) -> Result<OverlapMatch> + Sync,
+    {
+        use rayon::prelude::*;
+
+        let shared_pairs: Vec<Result<(FactorListPartialImage, FactorListPartialImage)>> = pairs
+            .iter()
+            .map(|(upper, lower)| {
+                Ok((
+                    self.images[*upper].share_pixels()?,
+                    self.images[*lower].share_pixels()?,
+                ))
+            })
+            .collect();
+
+        shared_pairs
+            .into_par_iter()
+            .map(|pair| {
+                let (upper, lower) = pair?;
+
+                match_pair(&upper, &lower)
+            })
+            .collect()
+    }
+
+    fn match_pair(&self, upper_index: usize, lower_index: usize) -> Result<OverlapMatch> {
+        self.images[upper_index].detect_aligned_match(
+            &self.images[lower_index],
+            &*self.overlap_matcher,
+            self.alignment.as_ref(),
+            self.scroll_bar.as_ref(),
+        )
+    }
+
+    pub fn sort_by_overlap(&mut self) -> Result<Vec<usize>> {
+        let order = self.infer_order()?;
+        self.images = order.iter().map(|i| self.images[*i].clone()).collect();
+
+        Ok(order)
+    }
+}
+
+// Chains the images from the top, leaving out or marking the pairs without an overlap as the
+// gap policy says. `match_pair` matches the pairs which only come up after an image is skipped.
+fn link_images<F>(
+    images_count: usize,
+    gap_policy: GapPolicy,
+    pair_matches: Vec<Result<OverlapMatch>>,
+    match_pair: F,
+) -> Result<(Vec<usize>, Vec<PairLink>, Vec<usize>)>
+where
+    F: Fn(usize, usize) -> Result<OverlapMatch>,
+{
+    let mut pair_matches: Vec<Option<Result<OverlapMatch>>> =
+        pair_matches.into_iter().map(Some).collect();
+
+    let mut chain = vec![0];
+    let mut links = Vec::new();
+    let mut skipped_indices = Vec::new();
+    let mut next_index = 1;
+
+    while next_index < images_count {
+        let upper_index = chain[chain.len() - 1];
+
+        // Consecutive pairs are matched up front, other pairs only appear after skipping.
+        let pair_match = if upper_index + 1 == next_index {
+            pair_matches[upper_index]
+                .take()
+                .ok_or(Error::ImageNotMatched)?
+        } else {
+            match_pair(upper_index, next_index)
+        };
+
+        match pair_match {
+            Ok(overlap_match) => {
+                chain.push(next_index);
+                links.push(PairLink::Matched(overlap_match));
+                next_index += 1;
+            }
+            Err(Error::ImageNotMatched) => match gap_policy {
+                GapPolicy::Fail => {
+                    return Err(Error::NoOverlapBetween {
+                        upper_index,
+                        lower_index: next_index,
+                    });
+                }
+                GapPolicy::InsertMarker => {
+                    chain.push(next_index);
+                    links.push(PairLink::Gap);
+                    next_index += 1;
+                }
+                GapPolicy::SkipOrphan => {
+                    if next_index + 1 >= images_count {
+                        skipped_indices.push(next_index);
+                        break;
+                    }
+
+                    let orphan_index = next_index;
+                    let retry_match = match_pair(upper_index, orphan_index + 1);
+
+                    match retry_match {
+                        Ok(overlap_match) => {
+                            skipped_indices.push(orphan_index);
+                            chain.push(orphan_index + 1);
+                            links.push(PairLink::Matched(overlap_match));
+                            next_index = orphan_index + 2;
+                        }
+                        Err(Error::ImageNotMatched) => {
+                            return Err(Error::NoOverlapBetween {
+                                upper_index,
+                                lower_index: orphan_index,
+                            });
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+            },
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok((chain, links, skipped_indices))
 }
 
 impl ImageMatrix for FactorListImage {
     fn convert_to_mat(&self) -> Result<Mat> {
-        self.images
-            .iter()
-            .skip(1)
-            .fold(
-                Ok(&mut self.images[0].clone()),
-                |first_image, second_image| -> Result<&mut FactorListPartialImage> {
-                    let first_image = first_image?;
-                    first_image.merge_below(second_image)?;
-
-                    Ok(first_image)
-                },
-            )?
-            .convert_to_mat()
+        self.stitch()?.0.convert_to_mat()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::align::Drift;
+    use super::*;
+
+    fn overlap_match(offset: i32) -> OverlapMatch {
+        OverlapMatch {
+            matched_point: MatchedPoint(offset, 0),
+            score: 0.99,
+            drift: Drift::default(),
+            estimated: false,
+        }
+    }
+
+    fn no_retry(upper_index: usize, lower_index: usize) -> Result<OverlapMatch> {
+        panic!(
+            "pair {}-{} is not expected to be matched",
+            upper_index, lower_index
+        )
+    }
+
+    #[test]
+    fn fail_policy_reports_the_pair_without_overlap() {
+        let pair_matches = vec![Ok(overlap_match(300)), Err(Error::ImageNotMatched)];
+
+        let result = link_images(3, GapPolicy::Fail, pair_matches, no_retry);
+        assert!(matches!(
+            result,
+            Err(Error::NoOverlapBetween {
+                upper_index: 1,
+                lower_index: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn insert_marker_policy_keeps_every_image() -> Result<()> {
+        let pair_matches = vec![Ok(overlap_match(300)), Err(Error::ImageNotMatched)];
+
+        let (chain, links, skipped_indices) =
+            link_images(3, GapPolicy::InsertMarker, pair_matches, no_retry)?;
+        assert_eq!(chain, vec![0, 1, 2]);
+        assert!(matches!(links[..], [PairLink::Matched(_), PairLink::Gap]));
+        assert!(skipped_indices.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn skip_orphan_policy_links_past_the_orphan() -> Result<()> {
+        let pair_matches = vec![
+            Ok(overlap_match(300)),
+            Err(Error::ImageNotMatched),
+            Err(Error::ImageNotMatched),
+        ];
+
+        let (chain, links, skipped_indices) =
+            link_images(4, GapPolicy::SkipOrphan, pair_matches, |upper, lower| {
+                assert_eq!((upper, lower), (1, 3));
+                Ok(overlap_match(250))
+            })?;
+        assert_eq!(chain, vec![0, 1, 3]);
+        assert!(matches!(
+            links[..],
+            [PairLink::Matched(_), PairLink::Matched(m)] if m.offset() == 250
+        ));
+        assert_eq!(skipped_indices, vec![2]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn skip_orphan_policy_drops_an_unmatched_last_image() -> Result<()> {
+        let pair_matches = vec![Ok(overlap_match(300)), Err(Error::ImageNotMatched)];
+
+        let (chain, links, skipped_indices) =
+            link_images(3, GapPolicy::SkipOrphan, pair_matches, no_retry)?;
+        assert_eq!(chain, vec![0, 1]);
+        assert_eq!(links.len(), 1);
+        assert_eq!(skipped_indices, vec![2]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn skip_orphan_policy_fails_when_the_next_image_does_not_match_either() {
+        let pair_matches = vec![
+            Ok(overlap_match(300)),
+            Err(Error::ImageNotMatched),
+            Err(Error::ImageNotMatched),
+        ];
+
+        let result = link_images(4, GapPolicy::SkipOrphan, pair_matches, |_, _| {
+            Err(Error::ImageNotMatched)
+        });
+        assert!(matches!(
+            result,
+            Err(Error::NoOverlapBetween {
+                upper_index: 1,
+                lower_index: 2
+            })
+        ));
     }
 }
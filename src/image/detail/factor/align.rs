@@ -0,0 +1,202 @@
+use opencv::core::{
+    min_max_loc, Mat, MatTraitConst, Point, Rect as cvRect, Scalar, BORDER_REPLICATE,
+};
+use opencv::imgproc;
+
+use crate::image::detail::factor::matcher::MatchedPoint;
+use crate::image::{Result, SimpleImage, SizeIdentifiableImage};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AlignmentConfig {
+    pub max_horizontal_drift: i32,
+    pub vertical_search_radius: i32,
+    pub template_height: i32,
+    pub min_score: f64,
+    // Sub-pixel shifts are only applied to peaks below this score and of at least this size, so
+    // pixel-exact screenshots are never interpolated.
+    pub exact_score: f64,
+    pub min_sub_pixel_shift: f64,
+}
+
+impl Default for AlignmentConfig {
+    fn default() -> Self {
+        Self {
+            max_horizontal_drift: 4,
+            vertical_search_radius: 3,
+            template_height: 48,
+            min_score: 0.9,
+            exact_score: 0.99,
+            min_sub_pixel_shift: 0.25,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct Drift {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Drift {
+    pub fn is_zero(&self) -> bool {
+        self.x.abs() < 0.01 && self.y.abs() < 0.01
+    }
+}
+
+// Residual shift of `lower` against `upper` around an already matched point: a row at `y` in
+// `lower` shows the content of `y + offset + drift.y` in `upper`, shifted right by `drift.x`.
+pub(crate) fn estimate_drift(
+    upper: &SimpleImage,
+    lower: &SimpleImage,
+    matched_point: MatchedPoint,
+    config: &AlignmentConfig,
+) -> Result<Drift> {
+    let MatchedPoint(upper_y, lower_y) = matched_point;
+    let margin = config.max_horizontal_drift.max(0);
+    let radius = config.vertical_search_radius.max(0);
+
+    let template_width = lower.width().min(upper.width()) - margin * 2;
+    let template_height = config
+        .template_height
+        .min(lower.height() - lower_y)
+        .min(upper.height() - upper_y - radius);
+    if template_width <= 0 || template_height < 8 || lower_y < 0 {
+        return Ok(Drift::default());
+    }
+
+    // The template is narrower than the searched band so it can slide sideways as well.
+    let template = Mat::roi(
+        &lower.0,
+        cvRect::new(margin, lower_y, template_width, template_height),
+    )?;
+    let search_top = (upper_y - radius).max(0);
+    let search_bottom = (upper_y + radius + template_height).min(upper.height());
+    let search_area = Mat::roi(
+        &upper.0,
+        cvRect::new(0, search_top, upper.width(), search_bottom - search_top),
+    )?;
+
+    let mut match_result = Mat::default();
+    imgproc::match_template(
+        &search_area,
+        &template,
+        &mut match_result,
+        imgproc::TM_CCOEFF_NORMED,
+        &Mat::default(),
+    )?;
+
+    let mut max_val = 0.0;
+    let mut max_loc = Point::default();
+    min_max_loc(
+        &match_result,
+        None,
+        Some(&mut max_val),
+        None,
+        Some(&mut max_loc),
+        &Mat::default(),
+    )?;
+    if max_val < config.min_score {
+        return Ok(Drift::default());
+    }
+
+    let score_at = |x: i32, y: i32| -> Result<Option<f64>> {
+        if x < 0 || y < 0 || x >= match_result.cols() || y >= match_result.rows() {
+            return Ok(None);
+        }
+
+        Ok(Some(*match_result.at_2d::<f32>(y, x)? as f64))
+    };
+
+    let sub_pixel = |fraction: f64| {
+        if max_val >= config.exact_score || fraction.abs() < config.min_sub_pixel_shift {
+            0.0
+        } else {
+            fraction
+        }
+    };
+    let sub_pixel_x = sub_pixel(parabolic_peak(
+        score_at(max_loc.x - 1, max_loc.y)?,
+        max_val,
+        score_at(max_loc.x + 1, max_loc.y)?,
+    ));
+    let sub_pixel_y = sub_pixel(parabolic_peak(
+        score_at(max_loc.x, max_loc.y - 1)?,
+        max_val,
+        score_at(max_loc.x, max_loc.y + 1)?,
+    ));
+
+    Ok(Drift {
+        x: (max_loc.x - margin) as f64 + sub_pixel_x,
+        y: (search_top + max_loc.y - upper_y) as f64 + sub_pixel_y,
+    })
+}
+
+fn parabolic_peak(before: Option<f64>, peak: f64, after: Option<f64>) -> f64 {
+    let (Some(before), Some(after)) = (before, after) else {
+        return 0.0;
+    };
+
+    let curvature = before - 2.0 * peak + after;
+    if curvature.abs() < f64::EPSILON {
+        return 0.0;
+    }
+
+    ((before - after) / (2.0 * curvature)).clamp(-0.5, 0.5)
+}
+
+pub(crate) fn translate(image: &Mat, x: f64, y: f64) -> Result<Mat> {
+    let transform = Mat::from_slice_2d(&[[1.0, 0.0, x], [0.0, 1.0, y]])?;
+    // Whole pixel shifts copy the pixels as they are.
+    let interpolation = if x.fract() == 0.0 && y.fract() == 0.0 {
+        imgproc::INTER_NEAREST
+    } else {
+        imgproc::INTER_LINEAR
+    };
+
+    let mut translated = Mat::default();
+    imgproc::warp_affine(
+        image,
+        &mut translated,
+        &transform,
+        image.size()?,
+        interpolation,
+        BORDER_REPLICATE,
+        Scalar::default(),
+    )?;
+
+    Ok(translated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parabolic_peak_finds_vertex_between_samples() {
+        // Samples of -(x - 0.25)^2 at -1, 0 and 1.
+        let shift = parabolic_peak(Some(-1.5625), -0.0625, Some(-0.5625));
+
+        assert!((shift - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn parabolic_peak_of_symmetric_samples_is_centered() {
+        assert_eq!(parabolic_peak(Some(0.8), 1.0, Some(0.8)), 0.0);
+    }
+
+    #[test]
+    fn parabolic_peak_needs_both_neighbors() {
+        assert_eq!(parabolic_peak(None, 1.0, Some(0.8)), 0.0);
+        assert_eq!(parabolic_peak(Some(0.8), 1.0, None), 0.0);
+    }
+
+    #[test]
+    fn parabolic_peak_of_flat_samples_is_centered() {
+        assert_eq!(parabolic_peak(Some(0.0), 1.0, Some(2.0)), 0.0);
+    }
+
+    #[test]
+    fn parabolic_peak_stays_within_half_a_pixel() {
+        assert_eq!(parabolic_peak(Some(0.0), 1.0, Some(1.5)), 0.5);
+    }
+}
@@ -0,0 +1,150 @@
+use opencv::core::{mean_std_dev, Mat, MatTraitConst, Rect as cvRect};
+use opencv::imgproc;
+
+use crate::image::detail::factor::scroll::ScrollBarConfig;
+use crate::image::detail::factor::FactorListPartialImage;
+use crate::image::detail::HorseGirlDetailImage;
+use crate::image::{Result, SizeIdentifiableImage};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CompletenessConfig {
+    pub header_search_ratio: f64,
+    pub header_green_ratio: f64,
+    pub scroll_bar_tolerance_ratio: f64,
+    pub end_padding_ratio: f64,
+    pub background_max_stddev: f64,
+}
+
+impl Default for CompletenessConfig {
+    fn default() -> Self {
+        Self {
+            header_search_ratio: 0.2,
+            header_green_ratio: 0.5,
+            scroll_bar_tolerance_ratio: 0.01,
+            end_padding_ratio: 0.02,
+            background_max_stddev: 4.0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CompletenessStatus {
+    Complete,
+    MissingStart,
+    MissingEnd,
+    MissingStartAndEnd,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Completeness {
+    pub header_visible: bool,
+    // `None` when no scroll bar was found, which does not count against the capture.
+    pub scroll_bar_at_top: Option<bool>,
+    pub scroll_bar_at_bottom: Option<bool>,
+    pub list_ends_above_footer: bool,
+}
+
+impl Completeness {
+    pub fn has_start(&self) -> bool {
+        self.header_visible && self.scroll_bar_at_top != Some(false)
+    }
+
+    pub fn has_end(&self) -> bool {
+        self.list_ends_above_footer && self.scroll_bar_at_bottom != Some(false)
+    }
+
+    pub fn status(&self) -> CompletenessStatus {
+        match (self.has_start(), self.has_end()) {
+            (true, true) => CompletenessStatus::Complete,
+            (false, true) => CompletenessStatus::MissingStart,
+            (true, false) => CompletenessStatus::MissingEnd,
+            (false, false) => CompletenessStatus::MissingStartAndEnd,
+        }
+    }
+}
+
+pub(crate) fn check(
+    first: &FactorListPartialImage,
+    last: &FactorListPartialImage,
+    scroll_bar: &ScrollBarConfig,
+    config: &CompletenessConfig,
+) -> Result<Completeness> {
+    let scroll_bar_tolerance = |image: &FactorListPartialImage| {
+        ((image.height() as f64 * config.scroll_bar_tolerance_ratio) as i32).max(2)
+    };
+
+    let scroll_bar_at_top = first
+        .detect_scroll_bar(scroll_bar)?
+        .map(|thumb| thumb.is_at_top(scroll_bar_tolerance(first)));
+    let scroll_bar_at_bottom = last
+        .detect_scroll_bar(scroll_bar)?
+        .map(|thumb| thumb.is_at_bottom(scroll_bar_tolerance(last)));
+
+    Ok(Completeness {
+        header_visible: is_header_visible(first, config)?,
+        scroll_bar_at_top,
+        scroll_bar_at_bottom,
+        list_ends_above_footer: ends_above_footer(last, config)?,
+    })
+}
+
+// The green 因子 header bar opens the list, so it is near the top of the first capture only
+// when the list was not scrolled yet.
+fn is_header_visible(image: &FactorListPartialImage, config: &CompletenessConfig) -> Result<bool> {
+    let list_area = image.factor_list_area;
+    let search_height = (list_area.height as f64 * config.header_search_ratio) as i32;
+    if search_height <= 0 || list_area.width <= 0 {
+        return Ok(false);
+    }
+
+    let search_area = Mat::roi(
+        &image.image_mat,
+        cvRect::new(list_area.x, list_area.y, list_area.width, search_height),
+    )?;
+
+    let mut hsv_image = Mat::default();
+    imgproc::cvt_color(
+        &search_area,
+        &mut hsv_image,
+        imgproc::COLOR_BGR2HSV,
+        search_area.channels(),
+    )?;
+
+    let green_binary_image = HorseGirlDetailImage::green_binary_image(&hsv_image)?;
+    let green_ratios =
+        HorseGirlDetailImage::row_white_ratios(&green_binary_image, 0, list_area.width)?;
+
+    Ok(green_ratios
+        .iter()
+        .any(|green_ratio| *green_ratio >= config.header_green_ratio))
+}
+
+// At the end of the list the last 継承元 block is followed by some plain panel before the
+// footer, while a list cut by the screen edge has cards up to its last row.
+fn ends_above_footer(image: &FactorListPartialImage, config: &CompletenessConfig) -> Result<bool> {
+    let list_area = image.factor_list_area;
+    let padding_height = ((list_area.height as f64 * config.end_padding_ratio) as i32).max(1);
+    if list_area.height <= padding_height || list_area.width <= 0 {
+        return Ok(false);
+    }
+
+    for y in (list_area.bottom() - padding_height)..list_area.bottom() {
+        let row = Mat::roi(
+            &image.image_mat,
+            cvRect::new(list_area.x, y, list_area.width, 1),
+        )?;
+
+        let mut grayscale = Mat::default();
+        imgproc::cvt_color(&row, &mut grayscale, imgproc::COLOR_BGR2GRAY, 0)?;
+
+        let mut mean = Mat::default();
+        let mut stddev = Mat::default();
+        mean_std_dev(&grayscale, &mut mean, &mut stddev, &Mat::default())?;
+
+        if *stddev.at::<f64>(0)? > config.background_max_stddev {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
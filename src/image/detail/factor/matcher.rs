@@ -0,0 +1,704 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+#[cfg(feature = "image_debug")]
+use opencv::core::Scalar;
+use opencv::core::{reduce, Mat, MatTraitConst, Rect as cvRect, Size, NORM_HAMMING, REDUCE_MAX};
+use opencv::features2d::{BFMatcher, ORB_ScoreType, ORB};
+use opencv::imgproc;
+use opencv::prelude::*;
+use opencv::types::{VectorOfDMatch, VectorOfKeyPoint};
+
+use crate::image::detail::factor::align::Drift;
+use crate::image::{CropHeight, CropY, Error, Result, SimpleImage, SizeIdentifiableImage};
+#[cfg(feature = "image_debug")]
+use crate::image::{ImageMatrix, Rect};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MatchedPoint(pub i32, pub i32);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct OverlapMatch {
+    pub matched_point: MatchedPoint,
+    pub score: f64,
+    pub drift: Drift,
+    // Placed from the scroll bars alone, without any matching content.
+    pub estimated: bool,
+}
+
+impl OverlapMatch {
+    pub fn offset(&self) -> i32 {
+        self.matched_point.0 - self.matched_point.1
+    }
+}
+
+// Offset expected from elsewhere, like the scroll bar, which matchers may use to look only
+// around it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct OffsetHint {
+    pub offset: i32,
+    pub tolerance: i32,
+}
+
+pub trait OverlapMatcher: Debug + Send + Sync {
+    fn find_overlap(&self, upper: &SimpleImage, lower: &SimpleImage) -> Result<OverlapMatch>;
+
+    fn find_overlap_near(
+        &self,
+        upper: &SimpleImage,
+        lower: &SimpleImage,
+        _hint: OffsetHint,
+    ) -> Result<OverlapMatch> {
+        self.find_overlap(upper, lower)
+    }
+
+    // The matcher without any fallback, for searches over pairs which mostly do not overlap,
+    // where a loose fallback finds false matches in the repeated card layout.
+    fn primary(&self) -> Option<&dyn OverlapMatcher> {
+        None
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ScanOrder {
+    BottomUp,
+    TopDown,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct TemplateOverlapMatcher {
+    pub height_partition_num: i32,
+    pub matching_threshold: f64,
+    pub scan_order: ScanOrder,
+    pub coarse_scale: f64,
+    pub coarse_threshold_margin: f64,
+    pub refine_radius: i32,
+    pub offset_tolerance: i32,
+    pub fail_on_ambiguity: bool,
+}
+
+impl Default for TemplateOverlapMatcher {
+    fn default() -> Self {
+        Self {
+            height_partition_num: 10,
+            matching_threshold: 0.95,
+            scan_order: ScanOrder::BottomUp,
+            coarse_scale: 0.25,
+            coarse_threshold_margin: 0.1,
+            refine_radius: 8,
+            offset_tolerance: 2,
+            fail_on_ambiguity: true,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct OffsetCandidate {
+    partition_num: i32,
+    upper_pos: i32,
+    lower_pos: i32,
+    score: f64,
+}
+
+impl OffsetCandidate {
+    fn offset(&self) -> i32 {
+        self.upper_pos - self.lower_pos
+    }
+}
+
+impl TemplateOverlapMatcher {
+    // Scores of every vertical position of `template` in `image`, like a row by row scan but in
+    // a single `match_template` call.
+    fn vertical_scores(image: &Mat, template: &Mat) -> Result<Vec<f32>> {
+        let mut match_result = Mat::default();
+        imgproc::match_template(
+            image,
+            template,
+            &mut match_result,
+            imgproc::TM_CCOEFF_NORMED,
+            &Mat::default(),
+        )?;
+
+        let mut row_max_scores = Mat::default();
+        reduce(&match_result, &mut row_max_scores, 1, REDUCE_MAX, -1)?;
+
+        (0..row_max_scores.rows())
+            .map(|y| Ok(*row_max_scores.at::<f32>(y)?))
+            .collect()
+    }
+
+    fn peaks(scores: &[f32], threshold: f64) -> Vec<i32> {
+        (0..scores.len())
+            .filter(|i| {
+                let score = scores[*i];
+                score as f64 > threshold
+                    && (*i == 0 || score >= scores[i - 1])
+                    && (*i + 1 == scores.len() || score > scores[i + 1])
+            })
+            .map(|i| i as i32)
+            .collect()
+    }
+
+    fn best_in_window(
+        &self,
+        upper: &Mat,
+        partition: &Mat,
+        window_start: i32,
+        window_end: i32,
+    ) -> Result<Option<(i32, f64)>> {
+        let window = Mat::roi(
+            upper,
+            cvRect::new(
+                0,
+                window_start,
+                upper.cols(),
+                window_end - window_start + partition.rows(),
+            ),
+        )?;
+        let scores = Self::vertical_scores(&window, partition)?;
+
+        Ok((window_start..window_end)
+            .map(|pos| (pos, scores[(pos - window_start) as usize] as f64))
+            .filter(|(_, score)| *score > self.matching_threshold)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b)))
+    }
+
+    fn search_partition(
+        &self,
+        upper: &Mat,
+        partition: &Mat,
+        coarse_upper: Option<&Mat>,
+        coarse_partition: Option<&Mat>,
+    ) -> Result<Vec<(i32, f64)>> {
+        // The last position is left out to stay aligned with the row by row scan.
+        let scanning_end = upper.rows() - partition.rows();
+        if scanning_end <= 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut candidates: Vec<(i32, f64)> = Vec::new();
+
+        if let (Some(coarse_upper), Some(coarse_partition)) = (coarse_upper, coarse_partition) {
+            if coarse_upper.rows() > coarse_partition.rows() {
+                let coarse_scores = Self::vertical_scores(coarse_upper, coarse_partition)?;
+                let coarse_threshold = self.matching_threshold - self.coarse_threshold_margin;
+
+                for coarse_pos in Self::peaks(&coarse_scores, coarse_threshold) {
+                    let estimated_pos = (coarse_pos as f64 / self.coarse_scale).round() as i32;
+                    let window_start = (estimated_pos - self.refine_radius).max(0);
+                    let window_end = (estimated_pos + self.refine_radius + 1).min(scanning_end);
+                    if window_end <= window_start {
+                        continue;
+                    }
+
+                    if let Some(refined) =
+                        self.best_in_window(upper, partition, window_start, window_end)?
+                    {
+                        let is_known = candidates
+                            .iter()
+                            .any(|(pos, _)| (pos - refined.0).abs() <= self.refine_radius);
+                        if !is_known {
+                            candidates.push(refined);
+                        }
+                    }
+                }
+            }
+        }
+
+        if candidates.is_empty() {
+            let scores = Self::vertical_scores(upper, partition)?;
+            let scores = &scores[..scanning_end as usize];
+
+            candidates = Self::peaks(scores, self.matching_threshold)
+                .into_iter()
+                .map(|pos| (pos, scores[pos as usize] as f64))
+                .collect();
+        }
+
+        Ok(candidates)
+    }
+
+    fn downscale(&self, image: &Mat) -> Result<Option<Mat>> {
+        if self.coarse_scale <= 0.0 || self.coarse_scale >= 1.0 {
+            return Ok(None);
+        }
+
+        let mut downscaled = Mat::default();
+        imgproc::resize(
+            image,
+            &mut downscaled,
+            Size::default(),
+            self.coarse_scale,
+            self.coarse_scale,
+            imgproc::INTER_AREA,
+        )?;
+
+        Ok(Some(downscaled))
+    }
+
+    fn collect_candidates(
+        &self,
+        upper: &SimpleImage,
+        lower: &SimpleImage,
+        partition_height: i32,
+    ) -> Result<Vec<OffsetCandidate>> {
+        let coarse_upper = self.downscale(&upper.0)?;
+        let coarse_lower = self.downscale(&lower.0)?;
+
+        let mut candidates = Vec::new();
+
+        for partition_num in 0..self.height_partition_num {
+            let lower_scanning_pos = partition_num * partition_height;
+            let lower_scanning_roi = lower
+                .vertical_crop_image(CropY(lower_scanning_pos), CropHeight(partition_height))?;
+
+            let coarse_partition = match &coarse_lower {
+                Some(coarse_lower) => {
+                    let coarse_pos = (lower_scanning_pos as f64 * self.coarse_scale) as i32;
+                    let coarse_height = ((partition_height as f64 * self.coarse_scale) as i32)
+                        .min(coarse_lower.rows() - coarse_pos);
+
+                    (coarse_height > 0)
+                        .then(|| {
+                            Mat::roi(
+                                coarse_lower,
+                                cvRect::new(0, coarse_pos, coarse_lower.cols(), coarse_height),
+                            )
+                        })
+                        .transpose()?
+                }
+                None => None,
+            };
+
+            let partition_candidates = self.search_partition(
+                &upper.0,
+                &lower_scanning_roi.0,
+                coarse_upper.as_ref(),
+                coarse_partition.as_ref(),
+            )?;
+
+            candidates.extend(partition_candidates.into_iter().map(|(upper_pos, score)| {
+                OffsetCandidate {
+                    partition_num,
+                    upper_pos,
+                    lower_pos: lower_scanning_pos,
+                    score,
+                }
+            }));
+        }
+
+        Ok(candidates)
+    }
+
+    fn match_overlap(
+        &self,
+        upper: &SimpleImage,
+        lower: &SimpleImage,
+        hint: Option<OffsetHint>,
+    ) -> Result<OverlapMatch> {
+        let partition_height = lower.height() / self.height_partition_num;
+        if partition_height <= 0 {
+            return Err(Error::ImageNotMatched);
+        }
+
+        let candidates = match hint {
+            Some(hint) => self.collect_candidates_near(upper, lower, partition_height, hint)?,
+            None => self.collect_candidates(upper, lower, partition_height)?,
+        };
+        let matched = self.select_consistent_candidate(candidates)?;
+
+        #[cfg(feature = "image_debug")]
+        {
+            let mut debug = upper.0.clone();
+            imgproc::rectangle(
+                &mut debug,
+                Rect::new(0, matched.upper_pos, upper.width(), partition_height).into(),
+                Scalar::new(0.0, 0.0, 255.0, 255.0),
+                2,
+                imgproc::LINE_8,
+                0,
+            )?;
+            SimpleImage(debug).write_to_file(
+                "debug-images",
+                format!(
+                    "self-matched-{}.png",
+                    chrono::Local::now().timestamp_millis()
+                )
+                .as_str(),
+            )?;
+        }
+
+        Ok(OverlapMatch {
+            matched_point: MatchedPoint(matched.upper_pos, matched.lower_pos),
+            score: matched.score,
+            drift: Default::default(),
+            estimated: false,
+        })
+    }
+
+    fn collect_candidates_near(
+        &self,
+        upper: &SimpleImage,
+        lower: &SimpleImage,
+        partition_height: i32,
+        hint: OffsetHint,
+    ) -> Result<Vec<OffsetCandidate>> {
+        let scanning_end = upper.height() - partition_height;
+        let mut candidates = Vec::new();
+
+        for partition_num in 0..self.height_partition_num {
+            let lower_scanning_pos = partition_num * partition_height;
+            let expected_pos = lower_scanning_pos + hint.offset;
+            let window_start = (expected_pos - hint.tolerance).max(0);
+            let window_end = (expected_pos + hint.tolerance + 1).min(scanning_end);
+            if window_end <= window_start {
+                continue;
+            }
+
+            let lower_scanning_roi = lower
+                .vertical_crop_image(CropY(lower_scanning_pos), CropHeight(partition_height))?;
+
+            if let Some((upper_pos, score)) =
+                self.best_in_window(&upper.0, &lower_scanning_roi.0, window_start, window_end)?
+            {
+                candidates.push(OffsetCandidate {
+                    partition_num,
+                    upper_pos,
+                    lower_pos: lower_scanning_pos,
+                    score,
+                });
+            }
+        }
+
+        Ok(candidates)
+    }
+
+    // Repeated factor rows let a single partition match at several offsets, so the offset is
+    // decided by how many partitions agree on it.
+    fn select_consistent_candidate(
+        &self,
+        mut candidates: Vec<OffsetCandidate>,
+    ) -> Result<OffsetCandidate> {
+        candidates.sort_by_key(OffsetCandidate::offset);
+
+        let mut clusters: Vec<Vec<OffsetCandidate>> = Vec::new();
+        for candidate in candidates {
+            match clusters.last_mut() {
+                Some(cluster)
+                    if candidate.offset() - cluster[cluster.len() - 1].offset()
+                        <= self.offset_tolerance =>
+                {
+                    cluster.push(candidate)
+                }
+                _ => clusters.push(vec![candidate]),
+            }
+        }
+
+        let support = |cluster: &Vec<OffsetCandidate>| {
+            let mut partitions: Vec<i32> = cluster.iter().map(|c| c.partition_num).collect();
+            partitions.sort();
+            partitions.dedup();
+            partitions.len()
+        };
+
+        let max_support = clusters
+            .iter()
+            .map(support)
+            .max()
+            .ok_or(Error::ImageNotMatched)?;
+        let mut best_clusters: Vec<Vec<OffsetCandidate>> = clusters
+            .into_iter()
+            .filter(|cluster| support(cluster) == max_support)
+            .collect();
+
+        if best_clusters.len() > 1 && self.fail_on_ambiguity {
+            return Err(Error::AmbiguousOverlap {
+                offsets: best_clusters
+                    .iter()
+                    .map(|cluster| cluster[cluster.len() / 2].offset())
+                    .collect(),
+            });
+        }
+
+        let best_cluster = match self.scan_order {
+            ScanOrder::BottomUp => best_clusters.pop(),
+            ScanOrder::TopDown => best_clusters.into_iter().next(),
+        }
+        .ok_or(Error::ImageNotMatched)?;
+
+        best_cluster
+            .into_iter()
+            .min_by(|a, b| {
+                a.partition_num
+                    .cmp(&b.partition_num)
+                    .then(b.score.total_cmp(&a.score))
+            })
+            .ok_or(Error::ImageNotMatched)
+    }
+}
+
+impl OverlapMatcher for TemplateOverlapMatcher {
+    fn find_overlap(&self, upper: &SimpleImage, lower: &SimpleImage) -> Result<OverlapMatch> {
+        self.match_overlap(upper, lower, None)
+    }
+
+    fn find_overlap_near(
+        &self,
+        upper: &SimpleImage,
+        lower: &SimpleImage,
+        hint: OffsetHint,
+    ) -> Result<OverlapMatch> {
+        // A wrong hint must not hide the real overlap, so the full search is the fallback.
+        match self.match_overlap(upper, lower, Some(hint)) {
+            Err(Error::ImageNotMatched) => self.match_overlap(upper, lower, None),
+            matched => matched,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct FeatureOverlapMatcher {
+    pub max_features: i32,
+    pub min_inliers: usize,
+    pub inlier_tolerance: f32,
+    pub max_horizontal_shift: f32,
+}
+
+impl Default for FeatureOverlapMatcher {
+    fn default() -> Self {
+        Self {
+            max_features: 1000,
+            min_inliers: 12,
+            inlier_tolerance: 2.0,
+            max_horizontal_shift: 4.0,
+        }
+    }
+}
+
+impl FeatureOverlapMatcher {
+    fn detect_features(&self, image: &SimpleImage) -> Result<(VectorOfKeyPoint, Mat)> {
+        let mut grayscale = Mat::default();
+        imgproc::cvt_color(&image.0, &mut grayscale, imgproc::COLOR_BGR2GRAY, 0)?;
+
+        let mut orb = ORB::create(
+            self.max_features,
+            1.2,
+            8,
+            31,
+            0,
+            2,
+            ORB_ScoreType::HARRIS_SCORE,
+            31,
+            20,
+        )?;
+
+        let mut keypoints = VectorOfKeyPoint::new();
+        let mut descriptors = Mat::default();
+        orb.detect_and_compute(
+            &grayscale,
+            &Mat::default(),
+            &mut keypoints,
+            &mut descriptors,
+            false,
+        )?;
+
+        Ok((keypoints, descriptors))
+    }
+
+    fn estimate_vertical_offset(&self, shifts: &[(f32, f32)]) -> Option<(i32, usize)> {
+        // Every match is tried as a hypothesis, which keeps the consensus deterministic while
+        // being cheap enough for the number of features detected on a list.
+        let mut inliers = shifts
+            .iter()
+            .map(|(_, hypothesis)| {
+                shifts
+                    .iter()
+                    .map(|(_, shift_y)| *shift_y)
+                    .filter(|shift_y| (shift_y - hypothesis).abs() <= self.inlier_tolerance)
+                    .collect::<Vec<f32>>()
+            })
+            .max_by_key(Vec::len)?;
+
+        if inliers.len() < self.min_inliers {
+            return None;
+        }
+
+        inliers.sort_by(f32::total_cmp);
+
+        Some((inliers[inliers.len() / 2].round() as i32, inliers.len()))
+    }
+}
+
+impl OverlapMatcher for FeatureOverlapMatcher {
+    fn find_overlap(&self, upper: &SimpleImage, lower: &SimpleImage) -> Result<OverlapMatch> {
+        let (upper_keypoints, upper_descriptors) = self.detect_features(upper)?;
+        let (lower_keypoints, lower_descriptors) = self.detect_features(lower)?;
+
+        if upper_descriptors.empty() || lower_descriptors.empty() {
+            return Err(Error::ImageNotMatched);
+        }
+
+        let matcher = BFMatcher::new(NORM_HAMMING, true)?;
+        let mut matches = VectorOfDMatch::new();
+        matcher.train_match(
+            &upper_descriptors,
+            &lower_descriptors,
+            &mut matches,
+            &Mat::default(),
+        )?;
+
+        let shifts: Vec<(f32, f32)> = matches
+            .iter()
+            .map(|m| {
+                let upper_point = upper_keypoints.get(m.query_idx as usize)?.pt();
+                let lower_point = lower_keypoints.get(m.train_idx as usize)?.pt();
+
+                Ok((upper_point.x - lower_point.x, upper_point.y - lower_point.y))
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .filter(|(shift_x, _)| shift_x.abs() <= self.max_horizontal_shift)
+            .collect();
+
+        let (scroll_offset, inliers_count) = self
+            .estimate_vertical_offset(&shifts)
+            .ok_or(Error::ImageNotMatched)?;
+
+        // The seam is placed in the middle of the overlapping rows.
+        let overlap_start = scroll_offset.max(0);
+        let overlap_end = upper.height().min(lower.height() + scroll_offset);
+        if overlap_end <= overlap_start {
+            return Err(Error::ImageNotMatched);
+        }
+
+        let upper_matching = (overlap_start + overlap_end) / 2;
+
+        Ok(OverlapMatch {
+            matched_point: MatchedPoint(upper_matching, upper_matching - scroll_offset),
+            score: inliers_count as f64 / matches.len() as f64,
+            drift: Default::default(),
+            estimated: false,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FallbackOverlapMatcher {
+    pub primary: Arc<dyn OverlapMatcher>,
+    pub fallback: Arc<dyn OverlapMatcher>,
+}
+
+impl Default for FallbackOverlapMatcher {
+    fn default() -> Self {
+        Self {
+            primary: Arc::new(TemplateOverlapMatcher::default()),
+            fallback: Arc::new(FeatureOverlapMatcher::default()),
+        }
+    }
+}
+
+impl OverlapMatcher for FallbackOverlapMatcher {
+    fn find_overlap(&self, upper: &SimpleImage, lower: &SimpleImage) -> Result<OverlapMatch> {
+        match self.primary.find_overlap(upper, lower) {
+            Err(Error::ImageNotMatched) => self.fallback.find_overlap(upper, lower),
+            matched => matched,
+        }
+    }
+
+    fn find_overlap_near(
+        &self,
+        upper: &SimpleImage,
+        lower: &SimpleImage,
+        hint: OffsetHint,
+    ) -> Result<OverlapMatch> {
+        match self.primary.find_overlap_near(upper, lower, hint) {
+            Err(Error::ImageNotMatched) => self.fallback.find_overlap_near(upper, lower, hint),
+            matched => matched,
+        }
+    }
+
+    fn primary(&self) -> Option<&dyn OverlapMatcher> {
+        Some(self.primary.primary().unwrap_or(&*self.primary))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(partition_num: i32, offset: i32, score: f64) -> OffsetCandidate {
+        OffsetCandidate {
+            partition_num,
+            upper_pos: offset + partition_num * 10,
+            lower_pos: partition_num * 10,
+            score,
+        }
+    }
+
+    #[test]
+    fn peaks_takes_one_position_per_plateau() {
+        let scores = [0.97, 0.9, 0.96, 0.96, 0.96, 0.5, 0.99, 0.99];
+
+        assert_eq!(TemplateOverlapMatcher::peaks(&scores, 0.95), vec![0, 4, 7]);
+    }
+
+    #[test]
+    fn peaks_ignores_scores_at_threshold() {
+        let scores = [0.5, 0.95, 0.5];
+
+        assert!(TemplateOverlapMatcher::peaks(&scores, 0.95).is_empty());
+    }
+
+    #[test]
+    fn majority_cluster_wins() -> Result<()> {
+        let matcher = TemplateOverlapMatcher::default();
+        let candidates = vec![
+            candidate(0, 300, 0.99),
+            candidate(1, 120, 0.96),
+            candidate(1, 301, 0.98),
+            candidate(2, 300, 0.97),
+        ];
+
+        let selected = matcher.select_consistent_candidate(candidates)?;
+        assert_eq!(selected.partition_num, 0);
+        assert_eq!(selected.offset(), 300);
+
+        Ok(())
+    }
+
+    #[test]
+    fn tied_clusters_are_ambiguous() {
+        let matcher = TemplateOverlapMatcher::default();
+        let candidates = vec![
+            candidate(0, 120, 0.99),
+            candidate(1, 121, 0.98),
+            candidate(0, 300, 0.97),
+            candidate(1, 300, 0.96),
+        ];
+
+        let result = matcher.select_consistent_candidate(candidates);
+        assert!(matches!(
+            result,
+            Err(Error::AmbiguousOverlap { ref offsets }) if offsets.len() == 2
+        ));
+    }
+
+    #[test]
+    fn tied_clusters_follow_scan_order_when_ambiguity_is_allowed() -> Result<()> {
+        let matcher = TemplateOverlapMatcher {
+            fail_on_ambiguity: false,
+            ..TemplateOverlapMatcher::default()
+        };
+        let candidates = vec![
+            candidate(0, 120, 0.99),
+            candidate(1, 120, 0.98),
+            candidate(0, 300, 0.97),
+            candidate(1, 300, 0.96),
+        ];
+
+        let selected = matcher.select_consistent_candidate(candidates)?;
+        assert_eq!(selected.offset(), 300);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,305 @@
+use std::ops::Range;
+
+use opencv::core::{
+    in_range, mean, reduce, Mat, MatTraitConst, Point, Rect as cvRect, Scalar, CV_32S, REDUCE_SUM,
+};
+use opencv::imgproc;
+
+use crate::image::detail::factor::matcher::OffsetHint;
+use crate::image::{Rect, Result};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ScrollBarConfig {
+    pub band_width_ratio: f64,
+    pub max_thumb_brightness: f64,
+    pub min_thumb_pixels: i32,
+    pub min_thumb_height: i32,
+    pub hint_tolerance_ratio: f64,
+    pub fallback_to_estimate: bool,
+}
+
+impl Default for ScrollBarConfig {
+    fn default() -> Self {
+        Self {
+            band_width_ratio: 0.04,
+            max_thumb_brightness: 150.0,
+            min_thumb_pixels: 2,
+            min_thumb_height: 8,
+            hint_tolerance_ratio: 0.05,
+            fallback_to_estimate: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScrollBarThumb {
+    pub x: Range<i32>,
+    pub y: Range<i32>,
+    pub track: Range<i32>,
+}
+
+impl ScrollBarThumb {
+    // The thumb is to the track what the visible rows are to the whole list, so its top tells
+    // how many rows of the list are scrolled past.
+    pub fn scroll_offset(&self) -> i32 {
+        let thumb_height = self.y.len().max(1) as f64;
+        let track_height = self.track.len() as f64;
+
+        ((self.y.start - self.track.start) as f64 * track_height / thumb_height).round() as i32
+    }
+
+    pub fn is_at_top(&self, tolerance: i32) -> bool {
+        self.y.start - self.track.start <= tolerance
+    }
+
+    pub fn is_at_bottom(&self, tolerance: i32) -> bool {
+        self.track.end - self.y.end <= tolerance
+    }
+}
+
+// The thumb is a thin dark bar right of the rows used for matching, which the list area
+// leaves out.
+pub(crate) fn detect_thumb(
+    image: &Mat,
+    list_area: &Rect,
+    config: &ScrollBarConfig,
+) -> Result<Option<ScrollBarThumb>> {
+    let band_start = list_area.right().min(image.cols());
+    let band_width = ((list_area.width as f64 * config.band_width_ratio).ceil() as i32)
+        .min(image.cols() - band_start);
+    if band_width <= 0 || list_area.height <= 0 {
+        return Ok(None);
+    }
+
+    let band = Mat::roi(
+        image,
+        cvRect::new(band_start, list_area.y, band_width, list_area.height),
+    )?;
+    let mut grayscale = Mat::default();
+    imgproc::cvt_color(&band, &mut grayscale, imgproc::COLOR_BGR2GRAY, 0)?;
+
+    let mut dark_mask = Mat::default();
+    in_range(
+        &grayscale,
+        &Scalar::all(0.0),
+        &Scalar::all(config.max_thumb_brightness),
+        &mut dark_mask,
+    )?;
+
+    let mut row_counts = Mat::default();
+    reduce(&dark_mask, &mut row_counts, 1, REDUCE_SUM, CV_32S)?;
+    let mut column_counts = Mat::default();
+    reduce(&dark_mask, &mut column_counts, 0, REDUCE_SUM, CV_32S)?;
+
+    let mut longest_run: Option<Range<i32>> = None;
+    let mut run_start = None;
+    for y in 0..=row_counts.rows() {
+        let is_thumb_row =
+            y < row_counts.rows() && *row_counts.at::<i32>(y)? / 255 >= config.min_thumb_pixels;
+
+        match (run_start, is_thumb_row) {
+            (None, true) => run_start = Some(y),
+            (Some(start), false) => {
+                if longest_run
+                    .as_ref()
+                    .is_none_or(|run| y - start > run.len() as i32)
+                {
+                    longest_run = Some(start..y);
+                }
+                run_start = None;
+            }
+            _ => {}
+        }
+    }
+
+    let Some(thumb_y) = longest_run.filter(|run| run.len() as i32 >= config.min_thumb_height)
+    else {
+        return Ok(None);
+    };
+    // A thumb as tall as the track means the whole list fits on screen, which looks the same
+    // as a dark border.
+    if thumb_y.len() as i32 >= list_area.height {
+        return Ok(None);
+    }
+
+    let mut thumb_columns = (0..column_counts.cols())
+        .map(|x| Ok((x, *column_counts.at::<i32>(x)? / 255)))
+        .collect::<Result<Vec<(i32, i32)>>>()?
+        .into_iter()
+        .filter(|(_, count)| *count >= config.min_thumb_height)
+        .map(|(x, _)| x);
+    let thumb_x_start = thumb_columns.next().unwrap_or(0);
+    let thumb_x_end = thumb_columns.next_back().unwrap_or(thumb_x_start) + 1;
+
+    Ok(Some(ScrollBarThumb {
+        x: (band_start + thumb_x_start)..(band_start + thumb_x_end),
+        y: (list_area.y + thumb_y.start)..(list_area.y + thumb_y.end),
+        track: list_area.y..list_area.bottom(),
+    }))
+}
+
+pub(crate) fn offset_hint(
+    upper: &ScrollBarThumb,
+    lower: &ScrollBarThumb,
+    config: &ScrollBarConfig,
+) -> OffsetHint {
+    OffsetHint {
+        offset: lower.scroll_offset() - upper.scroll_offset(),
+        tolerance: ((upper.track.len() as f64 * config.hint_tolerance_ratio) as i32).max(1),
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ScrollBarMode {
+    Keep,
+    #[default]
+    Erase,
+    RedrawFullLength,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ScrollBarStyle {
+    x: Range<i32>,
+    track_color: Scalar,
+    thumb_color: Scalar,
+}
+
+impl ScrollBarStyle {
+    // The track is sampled from the longer part of the band next to the thumb.
+    pub(crate) fn sample(image: &Mat, thumb: &ScrollBarThumb) -> Result<Self> {
+        let above = thumb.track.start..thumb.y.start;
+        let below = thumb.y.end..thumb.track.end;
+        let track_y = if above.len() >= below.len() {
+            above
+        } else {
+            below
+        };
+
+        let thumb_color = mean_color(image, &thumb.x, &thumb.y)?;
+        let track_color = if track_y.is_empty() {
+            thumb_color
+        } else {
+            mean_color(image, &thumb.x, &track_y)?
+        };
+
+        Ok(Self {
+            x: thumb.x.clone(),
+            track_color,
+            thumb_color,
+        })
+    }
+
+    // A capture taken mid-fade or over a popup can report a thumb far off the others, so the
+    // band is the median of all samples and the colors come from the sample closest to it.
+    pub(crate) fn median(styles: &[ScrollBarStyle]) -> Option<ScrollBarStyle> {
+        if styles.is_empty() {
+            return None;
+        }
+
+        let median_of = |edge: fn(&ScrollBarStyle) -> i32| {
+            let mut edges: Vec<i32> = styles.iter().map(edge).collect();
+            edges.sort();
+
+            let middle = edges.len() / 2;
+            if edges.len().is_multiple_of(2) {
+                (edges[middle - 1] + edges[middle]) / 2
+            } else {
+                edges[middle]
+            }
+        };
+
+        let x = median_of(|s| s.x.start)..median_of(|s| s.x.end);
+        let closest = styles
+            .iter()
+            .min_by_key(|s| (s.x.start - x.start).abs() + (s.x.end - x.end).abs())?;
+
+        Some(Self {
+            x,
+            track_color: closest.track_color,
+            thumb_color: closest.thumb_color,
+        })
+    }
+
+    pub(crate) fn repaint(&self, image: &mut Mat, mode: ScrollBarMode) -> Result<()> {
+        if mode == ScrollBarMode::Keep {
+            return Ok(());
+        }
+
+        // Anti-aliased edges of the thumb reach a pixel further on both sides.
+        let x_start = (self.x.start - 1).max(0);
+        let x_end = (self.x.end + 1).min(image.cols());
+        if x_end <= x_start {
+            return Ok(());
+        }
+
+        imgproc::rectangle(
+            image,
+            cvRect::new(x_start, 0, x_end - x_start, image.rows()),
+            self.track_color,
+            imgproc::FILLED,
+            imgproc::LINE_8,
+            0,
+        )?;
+
+        if mode == ScrollBarMode::RedrawFullLength {
+            let thumb_width = self.x.len() as i32;
+            let radius = thumb_width / 2;
+            let top = Point::new(self.x.start + radius, radius);
+            let bottom = Point::new(self.x.start + radius, image.rows() - radius - 1);
+
+            imgproc::line(
+                image,
+                top,
+                bottom,
+                self.thumb_color,
+                thumb_width,
+                imgproc::LINE_AA,
+                0,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn mean_color(image: &Mat, x: &Range<i32>, y: &Range<i32>) -> Result<Scalar> {
+    let area = Mat::roi(
+        image,
+        cvRect::new(x.start, y.start, x.len() as i32, y.len() as i32),
+    )?;
+
+    Ok(mean(&area, &Mat::default())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thumb(y: Range<i32>) -> ScrollBarThumb {
+        ScrollBarThumb {
+            x: 700..708,
+            y,
+            track: 100..1100,
+        }
+    }
+
+    #[test]
+    fn thumb_at_top_has_no_offset() {
+        assert_eq!(thumb(100..350).scroll_offset(), 0);
+    }
+
+    #[test]
+    fn offset_scales_thumb_top_by_track_to_thumb_ratio() {
+        assert_eq!(thumb(350..600).scroll_offset(), 1000);
+        assert_eq!(thumb(133..433).scroll_offset(), 110);
+    }
+
+    #[test]
+    fn thumb_at_bottom_is_detected_within_tolerance() {
+        let thumb = thumb(848..1098);
+
+        assert!(thumb.is_at_bottom(2));
+        assert!(!thumb.is_at_bottom(1));
+        assert!(!thumb.is_at_top(2));
+    }
+}
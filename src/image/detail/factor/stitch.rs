@@ -0,0 +1,529 @@
+use std::ops::Range;
+
+use opencv::core::{
+    absdiff, add_weighted, convert_scale_abs, reduce, Mat, MatTraitConst, Point, Rect as cvRect,
+    Scalar, BORDER_DEFAULT, CV_16S, CV_64F, REDUCE_SUM,
+};
+use opencv::imgproc;
+use opencv::types::VectorOfMat;
+
+use crate::image::detail::factor::align::{self, Drift};
+use crate::image::detail::factor::complete::Completeness;
+use crate::image::detail::factor::matcher::{MatchedPoint, OverlapMatch};
+use crate::image::detail::factor::validate::ValidationWarning;
+use crate::image::detail::factor::FactorListPartialImage;
+use crate::image::{Result, SimpleImage};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PairStitch {
+    pub upper_index: usize,
+    pub lower_index: usize,
+    pub matched_point: MatchedPoint,
+    pub score: f64,
+    pub drift: Drift,
+    pub estimated: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProvenanceSegment {
+    pub output_y: Range<i32>,
+    pub source_index: usize,
+    pub source_y: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StitchGap {
+    pub upper_index: usize,
+    pub lower_index: usize,
+    pub output_y: Range<i32>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StitchReport {
+    // Input index of every image, in the order they were stitched.
+    pub input_order: Vec<usize>,
+    pub pairs: Vec<PairStitch>,
+    pub provenance: Vec<ProvenanceSegment>,
+    pub gaps: Vec<StitchGap>,
+    pub skipped_indices: Vec<usize>,
+    pub warnings: Vec<ValidationWarning>,
+    pub completeness: Option<Completeness>,
+}
+
+impl StitchReport {
+    pub fn weak_pairs(&self, min_score: f64) -> impl Iterator<Item = &PairStitch> {
+        self.pairs.iter().filter(move |pair| pair.score < min_score)
+    }
+
+    pub fn estimated_pairs(&self) -> impl Iterator<Item = &PairStitch> {
+        self.pairs.iter().filter(|pair| pair.estimated)
+    }
+
+    pub(crate) fn map_indices<F: Fn(usize) -> usize>(&mut self, map: F) {
+        for index in self.input_order.iter_mut().chain(&mut self.skipped_indices) {
+            *index = map(*index);
+        }
+        for pair in &mut self.pairs {
+            pair.upper_index = map(pair.upper_index);
+            pair.lower_index = map(pair.lower_index);
+        }
+        for gap in &mut self.gaps {
+            gap.upper_index = map(gap.upper_index);
+            gap.lower_index = map(gap.lower_index);
+        }
+        for segment in &mut self.provenance {
+            segment.source_index = map(segment.source_index);
+        }
+    }
+
+    pub(crate) fn shift_output_y(&mut self, shift: i32) {
+        for segment in &mut self.provenance {
+            segment.output_y = (segment.output_y.start + shift)..(segment.output_y.end + shift);
+        }
+        for gap in &mut self.gaps {
+            gap.output_y = (gap.output_y.start + shift)..(gap.output_y.end + shift);
+        }
+    }
+
+    pub fn trace(&self, output_y: i32) -> Option<(usize, i32)> {
+        self.provenance
+            .iter()
+            .find(|segment| segment.output_y.contains(&output_y))
+            .map(|segment| {
+                (
+                    segment.source_index,
+                    segment.source_y + (output_y - segment.output_y.start),
+                )
+            })
+    }
+
+    pub fn seams(&self) -> impl Iterator<Item = i32> + '_ {
+        self.provenance
+            .iter()
+            .skip(1)
+            .map(|segment| segment.output_y.start)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum GapPolicy {
+    #[default]
+    Fail,
+    InsertMarker,
+    SkipOrphan,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum PairLink {
+    Matched(OverlapMatch),
+    Gap,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SeamConfig {
+    pub place_on_low_detail_row: bool,
+    pub min_margin: i32,
+    pub feather_height: i32,
+}
+
+impl Default for SeamConfig {
+    fn default() -> Self {
+        Self {
+            place_on_low_detail_row: true,
+            min_margin: 4,
+            feather_height: 0,
+        }
+    }
+}
+
+pub(crate) fn compose(
+    images: &[FactorListPartialImage],
+    chain: &[usize],
+    links: &[PairLink],
+    seam_config: &SeamConfig,
+) -> Result<(SimpleImage, StitchReport)> {
+    let mut composer = Composer::new(*seam_config);
+
+    for (chain_index, image_index) in chain.iter().enumerate() {
+        let link = chain_index
+            .checked_sub(1)
+            .and_then(|link_index| links.get(link_index));
+        composer.push(*image_index, &images[*image_index], link)?;
+    }
+
+    composer.finish()
+}
+
+#[derive(Debug)]
+struct CurrentImage {
+    index: usize,
+    aligned_mat: Mat,
+    segment_start: i32,
+    // Rows of the previous capture just below its seam, faded into this segment.
+    feather_band: Option<Mat>,
+}
+
+impl CurrentImage {
+    fn segment(&self, segment_end: i32) -> Result<Option<Mat>> {
+        if segment_end <= self.segment_start {
+            return Ok(None);
+        }
+
+        let segment = Mat::roi(
+            &self.aligned_mat,
+            cvRect::new(
+                0,
+                self.segment_start,
+                self.aligned_mat.cols(),
+                segment_end - self.segment_start,
+            ),
+        )?;
+
+        match &self.feather_band {
+            Some(band) => Ok(Some(feather(band, &segment)?)),
+            None => Ok(Some(segment)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Composer {
+    seam_config: SeamConfig,
+    report: StitchReport,
+    segments: VectorOfMat,
+    output_y: i32,
+    // Translation applied to the current capture so it lines up with the first one. Only the
+    // fractional part of the vertical drift is kept here, whole rows move the seam instead.
+    carried_drift: Drift,
+    current: Option<CurrentImage>,
+    image_positions: Vec<(usize, i32)>,
+}
+
+impl Composer {
+    pub(crate) fn new(seam_config: SeamConfig) -> Self {
+        Self {
+            seam_config,
+            report: StitchReport::default(),
+            segments: VectorOfMat::new(),
+            output_y: 0,
+            carried_drift: Drift::default(),
+            current: None,
+            image_positions: Vec::new(),
+        }
+    }
+
+    pub(crate) fn report(&self) -> &StitchReport {
+        &self.report
+    }
+
+    pub(crate) fn image_positions(&self) -> &[(usize, i32)] {
+        &self.image_positions
+    }
+
+    pub(crate) fn push(
+        &mut self,
+        index: usize,
+        image: &FactorListPartialImage,
+        link: Option<&PairLink>,
+    ) -> Result<()> {
+        let Some(current) = self.current.take() else {
+            self.carried_drift = Drift::default();
+            self.start_image(index, aligned_mat(image, self.carried_drift)?, 0, None);
+            return Ok(());
+        };
+
+        match link {
+            Some(PairLink::Matched(overlap_match)) => {
+                let aligned_offset =
+                    overlap_match.offset() as f64 + self.carried_drift.y + overlap_match.drift.y;
+                let offset = aligned_offset.round() as i32;
+                self.carried_drift = Drift {
+                    x: self.carried_drift.x + overlap_match.drift.x,
+                    y: aligned_offset - offset as f64,
+                };
+
+                let next_mat = aligned_mat(image, self.carried_drift)?;
+                let seam = place_seam(
+                    &current.aligned_mat,
+                    &next_mat,
+                    offset,
+                    overlap_match.matched_point.0,
+                    current.segment_start,
+                    &self.seam_config,
+                )?;
+                // When the next capture also overlaps rows used from the previous one, this
+                // capture contributes nothing and the next one continues from the same row.
+                let image_height = current.aligned_mat.rows();
+                let segment_end = seam.max(current.segment_start).min(image_height);
+
+                self.report.pairs.push(PairStitch {
+                    upper_index: current.index,
+                    lower_index: index,
+                    matched_point: overlap_match.matched_point,
+                    score: overlap_match.score,
+                    drift: overlap_match.drift,
+                    estimated: overlap_match.estimated,
+                });
+
+                let feather_height = self.seam_config.feather_height;
+                let feather_band = (feather_height > 0
+                    && segment_end + feather_height <= image_height)
+                    .then(|| {
+                        Mat::roi(
+                            &current.aligned_mat,
+                            cvRect::new(0, segment_end, current.aligned_mat.cols(), feather_height),
+                        )
+                    })
+                    .transpose()?;
+
+                self.commit(&current, segment_end)?;
+
+                let next_segment_start = (segment_end - offset).clamp(0, next_mat.rows());
+                self.start_image(index, next_mat, next_segment_start, feather_band);
+            }
+            _ => {
+                self.commit(&current, current.aligned_mat.rows())?;
+
+                let marker = missing_section_marker(&current.aligned_mat)?;
+                let marker_height = marker.rows();
+                self.segments.push(marker);
+
+                self.report.gaps.push(StitchGap {
+                    upper_index: current.index,
+                    lower_index: index,
+                    output_y: self.output_y..(self.output_y + marker_height),
+                });
+                self.output_y += marker_height;
+
+                self.carried_drift.y = 0.0;
+                self.start_image(index, aligned_mat(image, self.carried_drift)?, 0, None);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn preview(&self) -> Result<SimpleImage> {
+        let mut segments: VectorOfMat = self.segments.iter().collect();
+        if let Some(current) = &self.current {
+            if let Some(segment) = current.segment(current.aligned_mat.rows())? {
+                segments.push(segment);
+            }
+        }
+
+        concat_segments(&segments)
+    }
+
+    pub(crate) fn finish(mut self) -> Result<(SimpleImage, StitchReport)> {
+        if let Some(current) = self.current.take() {
+            self.commit(&current, current.aligned_mat.rows())?;
+        }
+
+        Ok((concat_segments(&self.segments)?, self.report))
+    }
+
+    fn start_image(
+        &mut self,
+        index: usize,
+        aligned_mat: Mat,
+        segment_start: i32,
+        feather_band: Option<Mat>,
+    ) {
+        self.image_positions
+            .push((index, self.output_y - segment_start));
+        self.current = Some(CurrentImage {
+            index,
+            aligned_mat,
+            segment_start,
+            feather_band,
+        });
+    }
+
+    fn commit(&mut self, current: &CurrentImage, segment_end: i32) -> Result<()> {
+        let Some(segment) = current.segment(segment_end)? else {
+            return Ok(());
+        };
+        let segment_height = segment.rows();
+
+        self.segments.push(segment);
+        self.report.provenance.push(ProvenanceSegment {
+            output_y: self.output_y..(self.output_y + segment_height),
+            source_index: current.index,
+            source_y: current.segment_start,
+        });
+        self.output_y += segment_height;
+
+        Ok(())
+    }
+}
+
+fn concat_segments(segments: &VectorOfMat) -> Result<SimpleImage> {
+    let mut merged_image = Mat::default();
+    opencv::core::vconcat(segments, &mut merged_image)?;
+
+    Ok(SimpleImage::new(merged_image))
+}
+
+fn aligned_mat(image: &FactorListPartialImage, drift: Drift) -> Result<Mat> {
+    if drift.is_zero() {
+        Ok(image.image_mat.clone())
+    } else {
+        align::translate(&image.image_mat, drift.x, drift.y)
+    }
+}
+
+// Picks the row in the overlap where a cut is least visible: both captures flat and agreeing
+// with each other, like the background between two factor cards.
+fn place_seam(
+    upper: &Mat,
+    lower: &Mat,
+    offset: i32,
+    matched_row: i32,
+    used_rows: i32,
+    seam_config: &SeamConfig,
+) -> Result<i32> {
+    if !seam_config.place_on_low_detail_row {
+        return Ok(matched_row);
+    }
+
+    let search_start = offset.max(0).max(used_rows) + seam_config.min_margin;
+    let search_end = upper.rows().min(lower.rows() + offset)
+        - seam_config.min_margin
+        - seam_config.feather_height.max(0);
+    if search_end <= search_start {
+        return Ok(matched_row);
+    }
+
+    let width = upper.cols().min(lower.cols());
+    let height = search_end - search_start;
+    let upper_band = grayscale(&Mat::roi(
+        upper,
+        cvRect::new(0, search_start, width, height),
+    )?)?;
+    let lower_band = grayscale(&Mat::roi(
+        lower,
+        cvRect::new(0, search_start - offset, width, height),
+    )?)?;
+
+    let upper_detail = row_sums(&detail_of(&upper_band)?)?;
+    let lower_detail = row_sums(&detail_of(&lower_band)?)?;
+
+    let mut difference = Mat::default();
+    absdiff(&upper_band, &lower_band, &mut difference)?;
+    let difference = row_sums(&difference)?;
+
+    let seam = (0..height as usize)
+        .map(|i| (i, upper_detail[i] + lower_detail[i] + difference[i]))
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(i, _)| search_start + i as i32)
+        .unwrap_or(matched_row);
+
+    Ok(seam)
+}
+
+fn grayscale(image: &Mat) -> Result<Mat> {
+    let mut grayscale = Mat::default();
+    imgproc::cvt_color(image, &mut grayscale, imgproc::COLOR_BGR2GRAY, 0)?;
+
+    Ok(grayscale)
+}
+
+fn detail_of(grayscale: &Mat) -> Result<Mat> {
+    let mut laplacian = Mat::default();
+    imgproc::laplacian(
+        grayscale,
+        &mut laplacian,
+        CV_16S,
+        3,
+        1.0,
+        0.0,
+        BORDER_DEFAULT,
+    )?;
+
+    let mut detail = Mat::default();
+    convert_scale_abs(&laplacian, &mut detail, 1.0, 0.0)?;
+
+    Ok(detail)
+}
+
+fn row_sums(image: &Mat) -> Result<Vec<f64>> {
+    let mut sums = Mat::default();
+    reduce(image, &mut sums, 1, REDUCE_SUM, CV_64F)?;
+
+    (0..sums.rows()).map(|y| Ok(*sums.at::<f64>(y)?)).collect()
+}
+
+fn feather(upper_band: &Mat, lower_segment: &Mat) -> Result<Mat> {
+    let band_height = upper_band.rows().min(lower_segment.rows());
+    let width = upper_band.cols().min(lower_segment.cols());
+
+    let mut rows = VectorOfMat::new();
+    for y in 0..band_height {
+        let upper_row = Mat::roi(upper_band, cvRect::new(0, y, width, 1))?;
+        let lower_row = Mat::roi(lower_segment, cvRect::new(0, y, width, 1))?;
+        let lower_weight = (y + 1) as f64 / (band_height + 1) as f64;
+
+        let mut blended_row = Mat::default();
+        add_weighted(
+            &upper_row,
+            1.0 - lower_weight,
+            &lower_row,
+            lower_weight,
+            0.0,
+            &mut blended_row,
+            -1,
+        )?;
+        rows.push(blended_row);
+    }
+
+    if band_height < lower_segment.rows() {
+        rows.push(Mat::roi(
+            lower_segment,
+            cvRect::new(0, band_height, width, lower_segment.rows() - band_height),
+        )?);
+    }
+
+    let mut feathered = Mat::default();
+    opencv::core::vconcat(&rows, &mut feathered)?;
+
+    Ok(feathered)
+}
+
+fn missing_section_marker(image: &Mat) -> Result<Mat> {
+    const MARKER_HEIGHT_RATIO: f64 = 0.08;
+
+    let marker_height = ((image.cols() as f64 * MARKER_HEIGHT_RATIO) as i32).max(24);
+    let mut marker = Mat::new_rows_cols_with_default(
+        marker_height,
+        image.cols(),
+        image.typ(),
+        Scalar::new(64.0, 64.0, 200.0, 255.0),
+    )?;
+
+    let stripe_step = marker_height;
+    for x in (-marker_height..image.cols()).step_by(stripe_step as usize) {
+        imgproc::line(
+            &mut marker,
+            Point::new(x, marker_height),
+            Point::new(x + marker_height, 0),
+            Scalar::new(48.0, 48.0, 160.0, 255.0),
+            marker_height / 6,
+            imgproc::LINE_AA,
+            0,
+        )?;
+    }
+
+    imgproc::put_text(
+        &mut marker,
+        "missing section",
+        Point::new(marker_height / 2, marker_height * 2 / 3),
+        imgproc::FONT_HERSHEY_SIMPLEX,
+        marker_height as f64 / 40.0,
+        Scalar::new(255.0, 255.0, 255.0, 255.0),
+        2,
+        imgproc::LINE_AA,
+        false,
+    )?;
+
+    Ok(marker)
+}
@@ -0,0 +1,194 @@
+use std::sync::Arc;
+
+use crate::image::detail::factor::align::AlignmentConfig;
+use crate::image::detail::factor::complete::{self, CompletenessConfig};
+use crate::image::detail::factor::matcher::OverlapMatcher;
+use crate::image::detail::factor::scroll::{ScrollBarConfig, ScrollBarMode, ScrollBarStyle};
+use crate::image::detail::factor::stitch::{Composer, GapPolicy, PairLink, StitchReport};
+use crate::image::detail::factor::validate::ValidationConfig;
+use crate::image::detail::factor::FactorListPartialImage;
+use crate::image::detail::{HorseGirlDetailImage, ImageConfig};
+use crate::image::{Error, Result, SimpleImage, SizeIdentifiableImage};
+
+#[derive(Debug)]
+pub struct StreamingStitcher {
+    overlap_matcher: Arc<dyn OverlapMatcher>,
+    alignment: Option<AlignmentConfig>,
+    scroll_bar: Option<ScrollBarConfig>,
+    scroll_bar_mode: ScrollBarMode,
+    scroll_bar_styles: Vec<ScrollBarStyle>,
+    gap_policy: GapPolicy,
+    validation: Option<ValidationConfig>,
+    completeness: Option<CompletenessConfig>,
+    composer: Composer,
+    first_image: Option<FactorListPartialImage>,
+    last_image: Option<(usize, FactorListPartialImage)>,
+    pushed_count: usize,
+    width: Option<i32>,
+    skipped_indices: Vec<usize>,
+}
+
+impl StreamingStitcher {
+    pub fn new(config: &ImageConfig) -> Self {
+        Self {
+            overlap_matcher: config.overlap_matcher.clone(),
+            alignment: config.alignment,
+            scroll_bar: config.scroll_bar,
+            scroll_bar_mode: config.scroll_bar_mode,
+            scroll_bar_styles: Vec::new(),
+            gap_policy: config.gap_policy,
+            validation: config.validation,
+            completeness: config.completeness,
+            composer: Composer::new(config.seam),
+            first_image: None,
+            last_image: None,
+            pushed_count: 0,
+            width: None,
+            skipped_indices: Vec::new(),
+        }
+    }
+
+    pub fn push_detail(&mut self, mut image: HorseGirlDetailImage) -> Result<usize> {
+        if let Some(width) = self.width {
+            image.scale_to_width(width)?;
+        }
+        if image.factor_list_area.is_none() {
+            image.calc_factor_list_area_by_layout()?;
+        }
+
+        self.push(FactorListPartialImage::from_detail(&image)?)
+    }
+
+    // Returns the index the image was given. With `GapPolicy::Fail` an image without overlap
+    // is rejected and the stitcher stays as it was, so the caller can push another capture.
+    pub fn push(&mut self, image: FactorListPartialImage) -> Result<usize> {
+        let index = self.pushed_count;
+
+        let link = match &self.last_image {
+            Some((last_index, last_image)) => {
+                let matched = last_image.detect_aligned_match(
+                    &image,
+                    &*self.overlap_matcher,
+                    self.alignment.as_ref(),
+                    self.scroll_bar.as_ref(),
+                );
+
+                match matched {
+                    Ok(overlap_match) => Some(PairLink::Matched(overlap_match)),
+                    Err(Error::ImageNotMatched) => match self.gap_policy {
+                        GapPolicy::Fail => {
+                            return Err(Error::NoOverlapBetween {
+                                upper_index: *last_index,
+                                lower_index: index,
+                            });
+                        }
+                        GapPolicy::InsertMarker => Some(PairLink::Gap),
+                        GapPolicy::SkipOrphan => {
+                            self.pushed_count += 1;
+                            self.skipped_indices.push(index);
+                            return Ok(index);
+                        }
+                    },
+                    Err(e) => return Err(e),
+                }
+            }
+            None => None,
+        };
+
+        self.composer.push(index, &image, link.as_ref())?;
+        self.update_scroll_bar_style(&image)?;
+
+        self.width.get_or_insert(image.width());
+        if self.first_image.is_none() {
+            self.first_image = Some(image.clone());
+        }
+        self.last_image = Some((index, image));
+        self.pushed_count += 1;
+
+        Ok(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pushed_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pushed_count == 0
+    }
+
+    pub fn preview(&self) -> Result<SimpleImage> {
+        if self.last_image.is_none() {
+            return Err(Error::NotEnoughImageSample);
+        }
+
+        let mut preview = self.composer.preview()?;
+        self.repaint_scroll_bar(&mut preview)?;
+
+        Ok(preview)
+    }
+
+    // Output row of the top of every stitched capture, in the order they were pushed.
+    pub fn image_positions(&self) -> &[(usize, i32)] {
+        self.composer.image_positions()
+    }
+
+    pub fn report(&self) -> StitchReport {
+        let mut report = self.composer.report().clone();
+        report.input_order = (0..self.pushed_count).collect();
+        report.skipped_indices = self.skipped_indices.clone();
+
+        report
+    }
+
+    pub fn finish(self) -> Result<(SimpleImage, StitchReport)> {
+        let Some((_, last_image)) = self.last_image else {
+            return Err(Error::NotEnoughImageSample);
+        };
+
+        let (mut merged_image, mut report) = self.composer.finish()?;
+        report.input_order = (0..self.pushed_count).collect();
+        report.skipped_indices = self.skipped_indices;
+
+        if let Some(style) = ScrollBarStyle::median(&self.scroll_bar_styles) {
+            style.repaint(&mut merged_image.0, self.scroll_bar_mode)?;
+        }
+
+        if let (Some(completeness), Some(first_image)) = (&self.completeness, &self.first_image) {
+            report.completeness = Some(complete::check(
+                first_image,
+                &last_image,
+                &self.scroll_bar.unwrap_or_default(),
+                completeness,
+            )?);
+        }
+
+        if let Some(validation) = &self.validation {
+            last_image.validate_merged(&merged_image, &mut report, validation)?;
+        }
+
+        Ok((merged_image, report))
+    }
+
+    fn update_scroll_bar_style(&mut self, image: &FactorListPartialImage) -> Result<()> {
+        if self.scroll_bar_mode == ScrollBarMode::Keep {
+            return Ok(());
+        }
+
+        let config = self.scroll_bar.unwrap_or_default();
+        let Some(thumb) = image.detect_scroll_bar(&config)? else {
+            return Ok(());
+        };
+
+        let style = ScrollBarStyle::sample(&image.image_mat, &thumb)?;
+        self.scroll_bar_styles.push(style);
+
+        Ok(())
+    }
+
+    fn repaint_scroll_bar(&self, image: &mut SimpleImage) -> Result<()> {
+        match ScrollBarStyle::median(&self.scroll_bar_styles) {
+            Some(style) => style.repaint(&mut image.0, self.scroll_bar_mode),
+            None => Ok(()),
+        }
+    }
+}
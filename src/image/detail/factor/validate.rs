@@ -0,0 +1,297 @@
+use std::ops::Range;
+
+use opencv::core::{min_max_loc, reduce, Mat, MatTraitConst, Rect as cvRect, CV_64F, REDUCE_AVG};
+use opencv::imgproc;
+
+use crate::image::detail::factor::stitch::StitchReport;
+use crate::image::{Result, SimpleImage};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ValidationConfig {
+    pub background_max_stddev: f64,
+    pub min_card_height_ratio: f64,
+    pub card_height_tolerance: f64,
+    pub spacing_tolerance: f64,
+    pub duplicate_similarity: f64,
+    pub fail_on_warning: bool,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            background_max_stddev: 4.0,
+            min_card_height_ratio: 0.03,
+            card_height_tolerance: 0.2,
+            spacing_tolerance: 0.25,
+            duplicate_similarity: 0.97,
+            fail_on_warning: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationWarning {
+    IrregularSpacing {
+        upper_y: Range<i32>,
+        lower_y: Range<i32>,
+        spacing: i32,
+        expected_spacing: i32,
+    },
+    DuplicatedRow {
+        upper_y: Range<i32>,
+        lower_y: Range<i32>,
+        seam_y: i32,
+        similarity: f64,
+    },
+}
+
+pub(crate) fn validate(
+    image: &SimpleImage,
+    list_x: Range<i32>,
+    report: &StitchReport,
+    config: &ValidationConfig,
+) -> Result<Vec<ValidationWarning>> {
+    let list_x = list_x.start.max(0)..list_x.end.min(image.0.cols());
+    if list_x.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let list_mat = Mat::roi(
+        &image.0,
+        cvRect::new(list_x.start, 0, list_x.len() as i32, image.0.rows()),
+    )?;
+    let mut grayscale = Mat::default();
+    imgproc::cvt_color(&list_mat, &mut grayscale, imgproc::COLOR_BGR2GRAY, 0)?;
+
+    let min_card_height = (list_x.len() as f64 * config.min_card_height_ratio) as i32;
+    let cards = find_card_rows(&grayscale, min_card_height.max(1), config)?;
+
+    let mut warnings = check_spacing(&cards, config);
+    warnings.extend(check_seam_duplicates(&grayscale, &cards, report, config)?);
+    warnings.sort_by_key(|warning| match warning {
+        ValidationWarning::IrregularSpacing { upper_y, .. }
+        | ValidationWarning::DuplicatedRow { upper_y, .. } => upper_y.start,
+    });
+
+    Ok(warnings)
+}
+
+// Rows of factor cards, separated by rows of the plain list background.
+fn find_card_rows(
+    grayscale: &Mat,
+    min_card_height: i32,
+    config: &ValidationConfig,
+) -> Result<Vec<Range<i32>>> {
+    let mut grayscale_f64 = Mat::default();
+    grayscale.convert_to(&mut grayscale_f64, CV_64F, 1.0, 0.0)?;
+    let mut squared = Mat::default();
+    opencv::core::multiply(&grayscale_f64, &grayscale_f64, &mut squared, 1.0, -1)?;
+
+    let mut row_means = Mat::default();
+    reduce(&grayscale_f64, &mut row_means, 1, REDUCE_AVG, CV_64F)?;
+    let mut row_squared_means = Mat::default();
+    reduce(&squared, &mut row_squared_means, 1, REDUCE_AVG, CV_64F)?;
+
+    let mut rows = Vec::new();
+    let mut run_start = None;
+
+    for y in 0..grayscale.rows() {
+        let mean = *row_means.at::<f64>(y)?;
+        let variance = (*row_squared_means.at::<f64>(y)? - mean * mean).max(0.0);
+        let is_background = variance.sqrt() <= config.background_max_stddev;
+
+        match (run_start, is_background) {
+            (None, false) => run_start = Some(y),
+            (Some(start), true) => {
+                if y - start >= min_card_height {
+                    rows.push(start..y);
+                }
+                run_start = None;
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(start) = run_start {
+        if grayscale.rows() - start >= min_card_height {
+            rows.push(start..grayscale.rows());
+        }
+    }
+
+    let mut heights: Vec<i32> = rows.iter().map(|row| row.len() as i32).collect();
+    heights.sort();
+    let Some(median_height) = heights.get(heights.len() / 2).copied() else {
+        return Ok(rows);
+    };
+
+    // Section headers and the parents' blocks have other heights and are not checked.
+    let tolerance = (median_height as f64 * config.card_height_tolerance) as i32;
+    Ok(rows
+        .into_iter()
+        .filter(|row| (row.len() as i32 - median_height).abs() <= tolerance)
+        .collect())
+}
+
+fn check_spacing(cards: &[Range<i32>], config: &ValidationConfig) -> Vec<ValidationWarning> {
+    let mut spacings: Vec<i32> = cards
+        .windows(2)
+        .map(|pair| pair[1].start - pair[0].start)
+        .collect();
+    spacings.sort();
+    let Some(expected_spacing) = spacings.get(spacings.len() / 2).copied() else {
+        return Vec::new();
+    };
+
+    let tolerance = (expected_spacing as f64 * config.spacing_tolerance) as i32;
+    cards
+        .windows(2)
+        .filter_map(|pair| {
+            let spacing = pair[1].start - pair[0].start;
+            // Larger jumps are gaps between sections rather than lost rows.
+            let is_irregular =
+                (spacing - expected_spacing).abs() > tolerance && spacing < expected_spacing * 3;
+
+            is_irregular.then(|| ValidationWarning::IrregularSpacing {
+                upper_y: pair[0].clone(),
+                lower_y: pair[1].clone(),
+                spacing,
+                expected_spacing,
+            })
+        })
+        .collect()
+}
+
+// Cards of the same layout look alike, so a card is only taken as duplicated when the match
+// offset also puts both sides of the seam on the same rows of the upper capture.
+fn check_seam_duplicates(
+    grayscale: &Mat,
+    cards: &[Range<i32>],
+    report: &StitchReport,
+    config: &ValidationConfig,
+) -> Result<Vec<ValidationWarning>> {
+    const NEIGHBOR_CARDS: usize = 2;
+
+    let mut warnings = Vec::new();
+
+    for segments in report.provenance.windows(2) {
+        let (upper_segment, lower_segment) = (&segments[0], &segments[1]);
+        let Some(pair) = report.pairs.iter().find(|pair| {
+            pair.upper_index == upper_segment.source_index
+                && pair.lower_index == lower_segment.source_index
+        }) else {
+            continue;
+        };
+        let offset = pair.matched_point.0 - pair.matched_point.1 + pair.drift.y.round() as i32;
+
+        let seam_y = lower_segment.output_y.start;
+        let first_below = cards.partition_point(|card| card.end <= seam_y);
+        let above = first_below.saturating_sub(NEIGHBOR_CARDS)..first_below;
+        let below = first_below..(first_below + NEIGHBOR_CARDS + 1).min(cards.len());
+
+        for upper in &cards[above] {
+            for lower in &cards[below.clone()] {
+                if lower.start < upper.end {
+                    continue;
+                }
+
+                let upper_source_y =
+                    upper.start - upper_segment.output_y.start + upper_segment.source_y;
+                let lower_source_y =
+                    lower.start - lower_segment.output_y.start + lower_segment.source_y + offset;
+                let tolerance = (upper.len() as f64 * config.card_height_tolerance) as i32;
+                if (upper_source_y - lower_source_y).abs() > tolerance {
+                    continue;
+                }
+
+                let similarity = row_similarity(grayscale, upper, lower)?;
+                if similarity >= config.duplicate_similarity {
+                    warnings.push(ValidationWarning::DuplicatedRow {
+                        upper_y: upper.clone(),
+                        lower_y: lower.clone(),
+                        seam_y,
+                        similarity,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(warnings)
+}
+
+fn row_similarity(grayscale: &Mat, upper: &Range<i32>, lower: &Range<i32>) -> Result<f64> {
+    let height = upper.len().min(lower.len()) as i32;
+    let upper_row = Mat::roi(
+        grayscale,
+        cvRect::new(0, upper.start, grayscale.cols(), height),
+    )?;
+    let lower_row = Mat::roi(
+        grayscale,
+        cvRect::new(0, lower.start, grayscale.cols(), height),
+    )?;
+
+    let mut match_result = Mat::default();
+    imgproc::match_template(
+        &upper_row,
+        &lower_row,
+        &mut match_result,
+        imgproc::TM_CCOEFF_NORMED,
+        &Mat::default(),
+    )?;
+
+    let mut max_val = 0.0;
+    min_max_loc(
+        &match_result,
+        None,
+        Some(&mut max_val),
+        None,
+        None,
+        &Mat::default(),
+    )?;
+
+    Ok(max_val)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cards(starts: &[i32]) -> Vec<Range<i32>> {
+        starts.iter().map(|start| *start..start + 80).collect()
+    }
+
+    #[test]
+    fn flags_spacing_off_the_median() {
+        let cards = cards(&[0, 100, 200, 260, 360]);
+
+        let warnings = check_spacing(&cards, &ValidationConfig::default());
+        assert_eq!(
+            warnings,
+            vec![ValidationWarning::IrregularSpacing {
+                upper_y: 200..280,
+                lower_y: 260..340,
+                spacing: 60,
+                expected_spacing: 100,
+            }]
+        );
+    }
+
+    #[test]
+    fn accepts_spacing_within_tolerance() {
+        let cards = cards(&[0, 100, 200, 280, 380]);
+
+        assert!(check_spacing(&cards, &ValidationConfig::default()).is_empty());
+    }
+
+    #[test]
+    fn takes_large_jumps_as_section_gaps() {
+        let cards = cards(&[0, 100, 200, 300, 900]);
+
+        assert!(check_spacing(&cards, &ValidationConfig::default()).is_empty());
+    }
+
+    #[test]
+    fn needs_two_cards_for_spacing() {
+        assert!(check_spacing(&cards(&[0]), &ValidationConfig::default()).is_empty());
+    }
+}
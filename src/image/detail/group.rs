@@ -0,0 +1,198 @@
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Duration, Local};
+use opencv::core::{min_max_loc, Mat, MatTraitConst, Size};
+use opencv::imgproc;
+
+use crate::image::detail::{HorseGirlDetailImage, HorseGirlFullDetailImage, ImageConfig};
+use crate::image::{Error, Rect, Result, SizeIdentifiableImage};
+
+#[derive(Debug, Copy, Clone)]
+pub struct GroupingConfig {
+    pub similarity_threshold: f64,
+    pub max_time_gap: Option<Duration>,
+}
+
+impl Default for GroupingConfig {
+    fn default() -> Self {
+        Self {
+            similarity_threshold: 0.9,
+            max_time_gap: Some(Duration::minutes(3)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StatusSignature(Mat);
+
+impl StatusSignature {
+    const SIGNATURE_SIZE: Size = Size {
+        width: 96,
+        height: 64,
+    };
+    const FALLBACK_STATUS_AREA_RATIO: f64 = 0.4;
+
+    pub fn from_detail(src: &HorseGirlDetailImage) -> Result<Self> {
+        let status_area_height = match src
+            .factor_list_area
+            .map(Ok)
+            .unwrap_or_else(|| src.detect_factor_list_area_by_layout())
+        {
+            Ok(list_area) => list_area.y,
+            Err(Error::ImageNotMatched) => {
+                (src.height() as f64 * Self::FALLBACK_STATUS_AREA_RATIO) as i32
+            }
+            Err(e) => return Err(e),
+        };
+
+        let status_area = Rect::new(0, 0, src.width(), status_area_height.max(1));
+        let status_mat = Mat::roi(&src.image_mat, status_area.into())?;
+
+        let mut grayscale = Mat::default();
+        imgproc::cvt_color(&status_mat, &mut grayscale, imgproc::COLOR_BGR2GRAY, 0)?;
+
+        let mut signature = Mat::default();
+        imgproc::resize(
+            &grayscale,
+            &mut signature,
+            Self::SIGNATURE_SIZE,
+            0.0,
+            0.0,
+            imgproc::INTER_AREA,
+        )?;
+
+        Ok(Self(signature))
+    }
+
+    pub fn similarity(&self, other: &Self) -> Result<f64> {
+        let mut match_result = Mat::default();
+        imgproc::match_template(
+            &self.0,
+            &other.0,
+            &mut match_result,
+            imgproc::TM_CCOEFF_NORMED,
+            &Mat::default(),
+        )?;
+
+        let mut max_val = 0.0;
+        min_max_loc(
+            &match_result,
+            None,
+            Some(&mut max_val),
+            None,
+            None,
+            &Mat::default(),
+        )?;
+
+        Ok(max_val)
+    }
+}
+
+#[derive(Debug)]
+struct HorseGroup {
+    signature: StatusSignature,
+    last_captured_at: Option<DateTime<Local>>,
+    images: Vec<HorseGirlDetailImage>,
+}
+
+pub fn group_by_horse<I>(
+    images: I,
+    grouping_config: &GroupingConfig,
+    config: ImageConfig,
+) -> Result<Vec<HorseGirlFullDetailImage>>
+where
+    I: IntoIterator<Item = HorseGirlDetailImage>,
+{
+    group_by_horse_with_timestamps(
+        images.into_iter().map(|image| (image, None)),
+        grouping_config,
+        config,
+    )
+}
+
+pub fn group_by_horse_with_timestamps<I>(
+    images: I,
+    grouping_config: &GroupingConfig,
+    config: ImageConfig,
+) -> Result<Vec<HorseGirlFullDetailImage>>
+where
+    I: IntoIterator<Item = (HorseGirlDetailImage, Option<DateTime<Local>>)>,
+{
+    let mut images: Vec<(HorseGirlDetailImage, Option<DateTime<Local>>)> =
+        images.into_iter().collect();
+    images.sort_by_key(|(_, captured_at)| *captured_at);
+
+    let mut groups: Vec<HorseGroup> = Vec::new();
+
+    for (image, captured_at) in images {
+        let signature = StatusSignature::from_detail(&image)?;
+
+        let mut best_group: Option<(usize, f64)> = None;
+        for (group_index, group) in groups.iter().enumerate() {
+            let is_within_time_gap = match (
+                grouping_config.max_time_gap,
+                group.last_captured_at,
+                captured_at,
+            ) {
+                (Some(max_time_gap), Some(last_captured_at), Some(captured_at)) => {
+                    captured_at - last_captured_at <= max_time_gap
+                }
+                _ => true,
+            };
+            if !is_within_time_gap {
+                continue;
+            }
+
+            let similarity = group.signature.similarity(&signature)?;
+            if similarity < grouping_config.similarity_threshold {
+                continue;
+            }
+            if best_group.is_none_or(|(_, best)| similarity > best) {
+                best_group = Some((group_index, similarity));
+            }
+        }
+
+        match best_group {
+            Some((group_index, _)) => {
+                let group = &mut groups[group_index];
+                group.images.push(image);
+                group.last_captured_at = captured_at.or(group.last_captured_at);
+            }
+            None => groups.push(HorseGroup {
+                signature,
+                last_captured_at: captured_at,
+                images: vec![image],
+            }),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|group| HorseGirlFullDetailImage::from_images(group.images, config.clone()))
+        .collect()
+}
+
+pub fn group_paths_by_horse<I, P>(
+    paths: I,
+    grouping_config: &GroupingConfig,
+    config: ImageConfig,
+) -> Result<Vec<HorseGirlFullDetailImage>>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+{
+    let images: Vec<(HorseGirlDetailImage, Option<DateTime<Local>>)> = paths
+        .into_iter()
+        .map(|path| {
+            let captured_at = fs::metadata(path.as_ref())?
+                .modified()
+                .ok()
+                .map(DateTime::<Local>::from);
+
+            Ok((HorseGirlDetailImage::from_path(path)?, captured_at))
+        })
+        .collect::<Result<_>>()?;
+
+    group_by_horse_with_timestamps(images, grouping_config, config)
+}
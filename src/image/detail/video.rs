@@ -0,0 +1,180 @@
+use std::path::Path;
+
+use opencv::core::{absdiff, count_non_zero, mean_std_dev, Mat, MatTraitConst, Size};
+use opencv::imgproc;
+use opencv::videoio::{VideoCapture, VideoCaptureTrait, VideoCaptureTraitConst, CAP_ANY};
+
+use crate::image::detail::HorseGirlDetailImage;
+use crate::image::{Error, Result};
+
+#[derive(Debug, Copy, Clone)]
+pub struct VideoSamplingConfig {
+    pub frame_step: i32,
+    pub min_changed_ratio: f64,
+    pub min_sharpness_ratio: f64,
+    pub max_frames: usize,
+}
+
+impl Default for VideoSamplingConfig {
+    fn default() -> Self {
+        Self {
+            frame_step: 2,
+            min_changed_ratio: 0.08,
+            min_sharpness_ratio: 0.6,
+            max_frames: 32,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct SampledFrame {
+    frame_mat: Mat,
+    thumbnail: Mat,
+}
+
+impl SampledFrame {
+    const THUMBNAIL_WIDTH: i32 = 270;
+    const DIFF_BRIGHTNESS_THRESHOLD: f64 = 70.0;
+
+    fn new(frame_mat: Mat) -> Result<Self> {
+        let mut grayscale = Mat::default();
+        imgproc::cvt_color(&frame_mat, &mut grayscale, imgproc::COLOR_BGR2GRAY, 0)?;
+
+        let ratio = Self::THUMBNAIL_WIDTH as f64 / grayscale.cols() as f64;
+        let mut thumbnail = Mat::default();
+        imgproc::resize(
+            &grayscale,
+            &mut thumbnail,
+            Size::default(),
+            ratio,
+            ratio,
+            imgproc::INTER_AREA,
+        )?;
+
+        Ok(Self {
+            frame_mat,
+            thumbnail,
+        })
+    }
+
+    fn sharpness(&self) -> Result<f64> {
+        let mut laplacian = Mat::default();
+        imgproc::laplacian(
+            &self.thumbnail,
+            &mut laplacian,
+            opencv::core::CV_64F,
+            1,
+            1.0,
+            0.0,
+            opencv::core::BORDER_DEFAULT,
+        )?;
+
+        let mut mean = Mat::default();
+        let mut stddev = Mat::default();
+        mean_std_dev(&laplacian, &mut mean, &mut stddev, &Mat::default())?;
+        let stddev = *stddev.at::<f64>(0)?;
+
+        Ok(stddev * stddev)
+    }
+
+    fn changed_ratio(&self, other: &Self) -> Result<f64> {
+        if self.thumbnail.size()? != other.thumbnail.size()? {
+            return Ok(1.0);
+        }
+
+        let mut diff = Mat::default();
+        absdiff(&self.thumbnail, &other.thumbnail, &mut diff)?;
+
+        let mut diff_threshold = Mat::default();
+        imgproc::threshold(
+            &diff,
+            &mut diff_threshold,
+            Self::DIFF_BRIGHTNESS_THRESHOLD,
+            255.0,
+            imgproc::THRESH_BINARY,
+        )?;
+
+        let changed_pixels = count_non_zero(&diff_threshold)?;
+        let all_pixels = diff_threshold.rows() * diff_threshold.cols();
+
+        Ok(changed_pixels as f64 / all_pixels as f64)
+    }
+}
+
+pub fn extract_detail_frames<P: AsRef<Path>>(
+    path: P,
+    sampling_config: &VideoSamplingConfig,
+) -> Result<Vec<HorseGirlDetailImage>> {
+    let path = path.as_ref().to_string_lossy();
+    let mut capture = VideoCapture::from_file(&path, CAP_ANY)?;
+    if !capture.is_opened()? {
+        return Err(Error::OpenVideoError {
+            path: path.to_string(),
+        });
+    }
+
+    let frame_step = sampling_config.frame_step.max(1);
+    let mut kept_frames: Vec<SampledFrame> = Vec::new();
+    let mut last_sharp_frame: Option<SampledFrame> = None;
+    let mut max_sharpness: f64 = 0.0;
+    let mut frame_index = 0;
+
+    loop {
+        let mut frame_mat = Mat::default();
+        if !capture.read(&mut frame_mat)? || frame_mat.empty() {
+            break;
+        }
+
+        frame_index += 1;
+        if (frame_index - 1) % frame_step != 0 {
+            continue;
+        }
+
+        let frame = SampledFrame::new(frame_mat)?;
+
+        let sharpness = frame.sharpness()?;
+        max_sharpness = max_sharpness.max(sharpness);
+        if sharpness < max_sharpness * sampling_config.min_sharpness_ratio {
+            continue;
+        }
+
+        let is_moved = match kept_frames.last() {
+            Some(last_kept_frame) => {
+                frame.changed_ratio(last_kept_frame)? >= sampling_config.min_changed_ratio
+            }
+            None => true,
+        };
+
+        if is_moved {
+            kept_frames.push(frame);
+            last_sharp_frame = None;
+            if kept_frames.len() >= sampling_config.max_frames {
+                break;
+            }
+        } else {
+            last_sharp_frame = Some(frame);
+        }
+    }
+
+    // The list usually settles at its end with only a small movement left, so the last sharp
+    // frame is kept as long as it differs from the last sampled one at all.
+    if let Some(frame) = last_sharp_frame {
+        let is_settled_frame = match kept_frames.last() {
+            Some(last_kept_frame) => frame.changed_ratio(last_kept_frame)? > 0.0,
+            None => false,
+        };
+
+        if is_settled_frame && kept_frames.len() < sampling_config.max_frames {
+            kept_frames.push(frame);
+        }
+    }
+
+    Ok(kept_frames
+        .into_iter()
+        .map(|frame| HorseGirlDetailImage {
+            image_mat: frame.frame_mat,
+            factor_list_area: Default::default(),
+            detected_list_area: Default::default(),
+        })
+        .collect())
+}
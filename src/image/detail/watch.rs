@@ -0,0 +1,312 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::image::detail::group::StatusSignature;
+use crate::image::detail::{HorseGirlDetailImage, HorseGirlFullDetailImage, ImageConfig};
+use crate::image::{Error, ImageMatrix, Result};
+
+#[derive(Debug, Clone)]
+pub struct WatchConfig {
+    pub poll_interval: Duration,
+    pub session_gap: Duration,
+    pub quiet_period: Duration,
+    pub similarity_threshold: f64,
+    pub extensions: Vec<String>,
+    pub output_suffix: String,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(2),
+            session_gap: Duration::from_secs(60),
+            quiet_period: Duration::from_secs(10),
+            similarity_threshold: 0.9,
+            extensions: vec!["png".to_string(), "jpg".to_string(), "jpeg".to_string()],
+            output_suffix: "_merged".to_string(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CaptureSession {
+    signature: StatusSignature,
+    paths: Vec<PathBuf>,
+    images: Vec<HorseGirlDetailImage>,
+    last_captured_at: SystemTime,
+    last_pushed_at: SystemTime,
+}
+
+#[derive(Debug)]
+pub struct MergedSession {
+    pub inputs: Vec<PathBuf>,
+    pub output: Result<PathBuf>,
+}
+
+#[derive(Debug)]
+pub struct DirectoryWatcher {
+    dir_path: PathBuf,
+    watch_config: WatchConfig,
+    config: ImageConfig,
+    seen_paths: HashSet<PathBuf>,
+    pending_files: HashMap<PathBuf, (u64, SystemTime)>,
+    sessions: Vec<CaptureSession>,
+}
+
+impl DirectoryWatcher {
+    pub fn new<P: AsRef<Path>>(
+        dir_path: P,
+        watch_config: WatchConfig,
+        config: ImageConfig,
+    ) -> Self {
+        Self {
+            dir_path: dir_path.as_ref().to_path_buf(),
+            watch_config,
+            config,
+            seen_paths: HashSet::new(),
+            pending_files: HashMap::new(),
+            sessions: Vec::new(),
+        }
+    }
+
+    pub fn skip_existing_files(&mut self) -> Result<()> {
+        let paths = self.list_capture_paths()?;
+        self.seen_paths.extend(paths);
+
+        Ok(())
+    }
+
+    pub fn run<F>(&mut self, mut on_merged: F) -> Result<()>
+    where
+        F: FnMut(&MergedSession) -> bool,
+    {
+        loop {
+            for merged_session in self.poll()? {
+                if !on_merged(&merged_session) {
+                    return Ok(());
+                }
+            }
+
+            thread::sleep(self.watch_config.poll_interval);
+        }
+    }
+
+    pub fn poll(&mut self) -> Result<Vec<MergedSession>> {
+        self.poll_at(SystemTime::now())
+    }
+
+    pub fn poll_at(&mut self, now: SystemTime) -> Result<Vec<MergedSession>> {
+        let mut new_paths: Vec<(SystemTime, PathBuf)> = Vec::new();
+        for path in self.list_capture_paths()? {
+            if self.seen_paths.contains(&path) {
+                continue;
+            }
+
+            // A truncated JPEG still decodes, so a file is only read once its size and
+            // modification time have not changed since the previous poll.
+            let metadata = fs::metadata(&path)?;
+            let file_state = (metadata.len(), metadata.modified()?);
+            if self.pending_files.insert(path.clone(), file_state) == Some(file_state) {
+                new_paths.push((file_state.1, path));
+            }
+        }
+        new_paths.sort();
+
+        for (captured_at, path) in new_paths {
+            // Files which are still being written cannot be decoded yet, so they are retried on
+            // the next poll.
+            let image = match HorseGirlDetailImage::from_path(&path) {
+                Ok(image) => image,
+                Err(Error::FileNotFound { .. }) | Err(Error::LoadImageFromFileError { .. }) => {
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            self.pending_files.remove(&path);
+            self.seen_paths.insert(path.clone());
+
+            self.push_capture(path, image, captured_at, now)?;
+        }
+
+        let (quiet_sessions, active_sessions): (Vec<CaptureSession>, Vec<CaptureSession>) =
+            self.sessions.drain(..).partition(|session| {
+                now.duration_since(session.last_pushed_at)
+                    .is_ok_and(|elapsed| elapsed >= self.watch_config.quiet_period)
+            });
+        self.sessions = active_sessions;
+
+        Ok(quiet_sessions
+            .into_iter()
+            .map(|session| self.merge_session(session))
+            .collect())
+    }
+
+    fn push_capture(
+        &mut self,
+        path: PathBuf,
+        image: HorseGirlDetailImage,
+        captured_at: SystemTime,
+        now: SystemTime,
+    ) -> Result<()> {
+        let signature = StatusSignature::from_detail(&image)?;
+
+        let mut matched_session = None;
+        for (session_index, session) in self.sessions.iter().enumerate() {
+            let time_gap = captured_at
+                .duration_since(session.last_captured_at)
+                .unwrap_or_default();
+            if time_gap > self.watch_config.session_gap {
+                continue;
+            }
+
+            if session.signature.similarity(&signature)? >= self.watch_config.similarity_threshold {
+                matched_session = Some(session_index);
+                break;
+            }
+        }
+
+        match matched_session {
+            Some(session_index) => {
+                let session = &mut self.sessions[session_index];
+                session.paths.push(path);
+                session.images.push(image);
+                session.last_captured_at = session.last_captured_at.max(captured_at);
+                session.last_pushed_at = now;
+            }
+            None => self.sessions.push(CaptureSession {
+                signature,
+                paths: vec![path],
+                images: vec![image],
+                last_captured_at: captured_at,
+                last_pushed_at: now,
+            }),
+        }
+
+        Ok(())
+    }
+
+    fn merge_session(&mut self, session: CaptureSession) -> MergedSession {
+        let output_path = self.output_path_of(&session.paths[0]);
+        let output = HorseGirlFullDetailImage::from_images(session.images, self.config.clone())
+            .and_then(|detail| {
+                let dir_path = output_path
+                    .parent()
+                    .map(|p| p.to_string_lossy().to_string())
+                    .unwrap_or_else(|| ".".to_string());
+                let file_name = output_path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default();
+
+                detail.write_to_file(&dir_path, &file_name)
+            })
+            .map(PathBuf::from);
+
+        if let Ok(output_path) = &output {
+            self.seen_paths.insert(output_path.clone());
+        }
+
+        MergedSession {
+            inputs: session.paths,
+            output,
+        }
+    }
+
+    fn output_path_of(&self, first_input_path: &Path) -> PathBuf {
+        let file_stem = first_input_path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        first_input_path.with_file_name(format!(
+            "{}{}.png",
+            file_stem, self.watch_config.output_suffix
+        ))
+    }
+
+    fn list_capture_paths(&self) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+
+        for entry in fs::read_dir(&self.dir_path)? {
+            let path = entry?.path();
+            if !path.is_file() {
+                continue;
+            }
+
+            let is_capture = path
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .is_some_and(|e| self.watch_config.extensions.contains(&e));
+            let is_output = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .is_some_and(|s| s.ends_with(&self.watch_config.output_suffix));
+
+            if is_capture && !is_output {
+                paths.push(path);
+            }
+        }
+
+        Ok(paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_IMAGES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/test_images");
+
+    #[test]
+    fn merges_session_after_quiet_period_only_once() -> Result<()> {
+        let dir_path = std::env::temp_dir().join(format!("uma-watch-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir_path);
+        fs::create_dir_all(&dir_path)?;
+
+        for i in 1..=4 {
+            let file_name = format!("{}.png", i);
+            fs::copy(
+                Path::new(TEST_IMAGES_DIR).join(&file_name),
+                dir_path.join(&file_name),
+            )?;
+        }
+
+        let watch_config = WatchConfig {
+            quiet_period: Duration::from_secs(10),
+            ..WatchConfig::default()
+        };
+        let mut watcher = DirectoryWatcher::new(&dir_path, watch_config, ImageConfig::default());
+
+        // The first poll only records the files, the second one reads them as they are unchanged.
+        let started_at = SystemTime::now();
+        assert!(watcher.poll_at(started_at)?.is_empty());
+        assert!(watcher
+            .poll_at(started_at + Duration::from_secs(5))?
+            .is_empty());
+        assert!(watcher
+            .poll_at(started_at + Duration::from_secs(10))?
+            .is_empty());
+
+        let merged_sessions = watcher.poll_at(started_at + Duration::from_secs(15))?;
+        assert_eq!(merged_sessions.len(), 1);
+        assert_eq!(merged_sessions[0].inputs.len(), 4);
+
+        let output_path = merged_sessions[0]
+            .output
+            .as_ref()
+            .expect("session is merged");
+        assert!(output_path.exists());
+        assert_eq!(output_path.file_name().unwrap(), "1_merged.png");
+
+        assert!(watcher
+            .poll_at(started_at + Duration::from_secs(30))?
+            .is_empty());
+
+        fs::remove_dir_all(&dir_path)?;
+
+        Ok(())
+    }
+}